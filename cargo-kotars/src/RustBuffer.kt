@@ -0,0 +1,47 @@
+import java.lang.ref.Reference
+import java.nio.ByteBuffer
+
+/**
+ * Direct [ByteBuffer] whose memory is owned by Rust. The memory is freed when the buffer is closed or, if it
+ * wasn't, once this object is collected, so the buffer is only handed out by [withBuffer].
+ */
+class RustBuffer private constructor(buffer: ByteBuffer, pointer: Long) : AutoCloseable {
+    private var openBuffer: ByteBuffer? = buffer
+    private val resource: NativeResource = buffer.capacity().let { capacity ->
+        thread.addObject(this, pointer, "RustBuffer") { RustBuffers.free(it, capacity) }
+    }
+
+    val isClosed: Boolean
+        get() = openBuffer == null
+
+    /**
+     * Runs [block] with the buffer, keeping this object reachable until it returns so the memory can't be freed
+     * by the collector while it's used.
+     *
+     * The buffer must not escape [block] nor be used after closing this object in it: it points to the memory
+     * of Rust, which is freed without invalidating the buffer, so using it afterwards reads or writes freed memory.
+     */
+    fun <R> withBuffer(block: (ByteBuffer) -> R): R {
+        val buffer = openBuffer ?: throw IllegalStateException("RustBuffer was already closed")
+
+        try {
+            return block(buffer)
+        } finally {
+            Reference.reachabilityFence(this)
+        }
+    }
+
+    override fun close() {
+        if (thread.contains(resource)) {
+            openBuffer = null
+            resource.close()
+            thread.remove(resource)
+        } else {
+            println("RustBuffer was already closed")
+        }
+    }
+}
+
+private object RustBuffers {
+    external fun free(pointer: Long, capacity: Int)
+}
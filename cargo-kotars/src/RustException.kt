@@ -0,0 +1 @@
+class RustException(message: String) : Exception(message)
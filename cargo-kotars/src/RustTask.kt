@@ -0,0 +1,28 @@
+import java.util.concurrent.CompletableFuture
+import kotlin.coroutines.resume
+import kotlin.coroutines.resumeWithException
+import kotlinx.coroutines.suspendCancellableCoroutine
+
+object RustTasks {
+    external fun cancel(task: Long)
+
+    external fun release(task: Long)
+}
+
+suspend fun <T> awaitRustTask(task: Long, future: CompletableFuture<T>): T {
+    try {
+        return suspendCancellableCoroutine { continuation ->
+            continuation.invokeOnCancellation { RustTasks.cancel(task) }
+
+            future.whenComplete { value, error ->
+                if (error != null) {
+                    continuation.resumeWithException(error)
+                } else {
+                    continuation.resume(value)
+                }
+            }
+        }
+    } finally {
+        RustTasks.release(task)
+    }
+}
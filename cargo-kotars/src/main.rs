@@ -1,131 +1,148 @@
+use std::collections::BTreeSet;
 use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use object::{Object, ObjectSection};
 
-use kotars_common::{Function, JniType, Parameter, RsInterface, RsStruct, string_to_camel_case};
+use kotars_common::{KOTARS_METADATA_DIR_ENV, METADATA_SECTION_ELF, METADATA_SECTION_MACHO, METADATA_SECTION_PE, ClassSync, Function, JniType, Parameter, RsClass, RsEnum, RsInterface, RsSealedClass, RsStruct, is_generated_tuple, string_to_camel_case, tuple_class_name};
+
+use crate::metadata::Binding;
+
+mod metadata;
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
     /// Path where the Kotlin source code files will be copied
-    #[arg(short, long)]
-    kotlin_output: String,
+    #[arg(short, long, required = true)]
+    kotlin_output: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
 }
 
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Generates the Kotlin code from the metadata embedded in a native library built with kotars
+    FromLib {
+        /// Path of the native library
+        library: PathBuf,
+
+        /// Path where the Kotlin source code files will be copied
+        #[arg(short, long)]
+        kotlin_output: String,
+    },
+}
 
-fn main() {
-    let args = Args::parse();
 
-    let mut command = Command::new("cargo");
-    command.arg("rustc");
-    command.arg("--profile");
-    command.arg("check");
-    command.arg("--");
-    command.arg("-Zunpretty=expanded");
+fn main() {
+    // Cargo passes the name of the subcommand when it's called as `cargo kotars`
+    let mut args = std::env::args().collect::<Vec<String>>();
+    if args.get(1).is_some_and(|arg| arg == "kotars") {
+        args.remove(1);
+    }
+    let args = Args::parse_from(args);
 
-    let dir = Path::new(&args.kotlin_output);
+    let (records, kotlin_output) = match args.command {
+        Some(Commands::FromLib { library, kotlin_output }) => (library_records(&library), kotlin_output),
+        None => {
+            let records = metadata_records().unwrap_or_else(|| {
+                println!("No kotars metadata found, falling back to expanding the source code (requires a nightly toolchain)");
+                expanded_source_records()
+            });
 
-    let package_name_line_prefix = "pub const JNI_PACKAGE_NAME: &str = \"";
-    create_base_files(dir);
+            (records, args.kotlin_output.expect("Kotlin output path is required"))
+        }
+    };
+    let dir = Path::new(&kotlin_output);
 
-    let res = command.output().expect("Output read failed");
-    let out_text = String::from_utf8_lossy(&res.stdout);
-    let err = String::from_utf8_lossy(&res.stderr);
-    let text = out_text.to_string();
-    let lines = text.split('\n');
+    let bindings = metadata::parse_bindings(records.iter().flat_map(|record| record.lines()))
+        .unwrap_or_else(|errors| {
+            for error in errors {
+                eprintln!("error: {error}");
+            }
+            std::process::exit(1);
+        });
 
-    if !err.is_empty() && text.is_empty() {
-        panic!("{err}")
+    let package_name = bindings.iter()
+        .find_map(|binding| match binding {
+            Binding::PackageName(package_name) => Some(package_name.clone()),
+            _ => None,
+        })
+        .expect("Package name not found in source");
+
+    // Kotlin files are placed in the directory tree matching their package
+    let package_dir = dir.join(package_name.replace('.', "/"));
+    let dir = package_dir.as_path();
+    std::fs::create_dir_all(dir).expect("Creating the package directory failed");
+    let has_async_functions = bindings.iter().any(|binding| matches!(binding, Binding::Function(func) if func.is_async));
+    create_base_files(dir, package_name.as_str(), has_async_functions);
+
+    let mut enums = Vec::new();
+    let mut functions = Vec::new();
+    let mut structs = Vec::new();
+    let mut data_classes = Vec::new();
+    let mut sealed_classes = Vec::new();
+    let mut interfaces = Vec::new();
+
+    for binding in bindings {
+        match binding {
+            Binding::PackageName(_) => {}
+            Binding::Enum(rs_enum) => enums.push(rs_enum),
+            Binding::Function(func) => functions.push(func),
+            Binding::Class(struc) => structs.push(struc),
+            Binding::DataClass(struc) => data_classes.push(struc),
+            Binding::SealedClass(sealed_class) => sealed_classes.push(sealed_class),
+            Binding::Interface(interface) => interfaces.push(interface),
+        }
     }
 
-    let package_name = lines.clone()
-        .filter_map(|line| {
-            if line.contains(package_name_line_prefix) {
-                let range_start = line.find(package_name_line_prefix).expect("Package name not found in line") + package_name_line_prefix.len();
-                let range_end = line.len() - 2;
-                let package_name = &line[range_start..range_end];
-                Some(package_name.to_string())
-            } else {
-                None
-            }
-        })
-        .collect::<Vec<String>>()
-        .first()
-        .expect("Package name not found in source")
-        .clone();
-
-    let functions = lines.clone() // todo do not clone
-        .filter_map(|line| {
-            if line.contains("JNI_FN_DATA") {
-                // let json = line.
-                let prefix_to_remove = "JNI_FN_DATA ";
-                let range_start = line.find(prefix_to_remove).expect("JNI_FN_DATA not found.") + prefix_to_remove.len();
-                let range_end = line.len() - 2;
-                let json_line = &line[range_start..range_end].replace('\\', "");
-
-                let func: Function = serde_json::from_str(json_line).unwrap_or_else(|_| panic!("Unable to deserialize function {json_line}"));
-                Some(func)
-            } else {
-                None
-            }
-        })
-        .collect::<Vec<Function>>();
-
-    let classes = lines.clone() // todo do not clone
-        .filter_map(|line| {
-            if line.contains("JNI_CLASS") {
-                let prefix_to_remove = "JNI_CLASS ";
-                let range_start = line.find(prefix_to_remove).expect("JNI_CLASS not found.") + prefix_to_remove.len();
-                let range_end = line.len() - 2;
-                let json_line = &line[range_start..range_end].replace('\\', "");
-                let struc: RsStruct = serde_json::from_str(json_line).unwrap_or_else(|_| panic!("Unable to deserialize class {json_line}"));
-                let functions = functions
-                    .iter()
-                    .filter(|func| func.owner_name == struc.name)
-                    .collect::<Vec<&Function>>();
-
-                Some((struc, functions))
-            } else {
-                None
-            }
-        })
-        .collect::<Vec<(RsStruct, Vec<&Function>)>>();
-
-    let data_classes = lines // todo do not clone
-        .clone()
-        .filter_map(|line| {
-            if line.contains("JNI_DATA_CLASS") {
-                // let json = line.
-                let prefix_to_remove = "JNI_DATA_CLASS ";
-                let range_start = line.find(prefix_to_remove).expect("JNI_DATA_CLASS not found.") + prefix_to_remove.len();
-                let range_end = line.len() - 2;
-                let json_line = &line[range_start..range_end].replace('\\', "");
-                let struc: RsStruct = serde_json::from_str(json_line).unwrap_or_else(|_| panic!("Unable to deserialize data class {json_line}"));
-                Some(struc)
-            } else {
-                None
-            }
-        })
-        .collect::<Vec<RsStruct>>();
-
-    let interfaces = lines
-        .filter_map(|line| {
-            if line.contains("JNI_INTERFACE") {
-                // let json = line.
-                let prefix_to_remove = "JNI_INTERFACE ";
-                let range_start = line.find(prefix_to_remove).expect("JNI_INTERFACE not found.") + prefix_to_remove.len();
-                let range_end = line.len() - 2;
-                let json_line = &line[range_start..range_end].replace('\\', "");
-                let struc: RsInterface = serde_json::from_str(json_line).unwrap_or_else(|err| panic!("Unable to deserialize interface {json_line}. {err}"));
-                Some(struc)
-            } else {
-                None
-            }
+    let enum_names = enums.iter()
+        .map(|rs_enum| rs_enum.name.clone())
+        .collect::<Vec<String>>();
+
+    functions.iter_mut().for_each(|func| func.resolve_enums(&enum_names));
+    data_classes.iter_mut().for_each(|struc| struc.resolve_enums(&enum_names));
+    sealed_classes.iter_mut().for_each(|sealed_class| sealed_class.resolve_enums(&enum_names));
+    interfaces.iter_mut()
+        .flat_map(|interface| interface.functions.iter_mut())
+        .for_each(|func| func.resolve_enums(&enum_names));
+
+    let classes = structs
+        .into_iter()
+        .map(|struc| {
+            let functions = functions
+                .iter()
+                .filter(|func| func.owner_name == struc.name)
+                .collect::<Vec<&Function>>();
+
+            (struc, functions)
         })
-        .collect::<Vec<RsInterface>>();
+        .collect::<Vec<(RsClass, Vec<&Function>)>>();
+
+    let mut tuple_arities = BTreeSet::new();
+    functions.iter().for_each(|func| func.collect_tuple_arities(&mut tuple_arities));
+    data_classes.iter().for_each(|data_class| data_class.collect_tuple_arities(&mut tuple_arities));
+    sealed_classes.iter().for_each(|sealed_class| sealed_class.collect_tuple_arities(&mut tuple_arities));
+    interfaces.iter()
+        .flat_map(|interface| interface.functions.iter())
+        .for_each(|func| func.collect_tuple_arities(&mut tuple_arities));
+
+    for arity in tuple_arities.into_iter().filter(|arity| is_generated_tuple(*arity)) {
+        create_tuple_class(dir, arity, package_name.as_str());
+    }
+
+    for rs_enum in enums {
+        create_enum_class(dir, &rs_enum, package_name.as_str());
+    }
+
+    for sealed_class in sealed_classes {
+        create_sealed_class(dir, &sealed_class, package_name.as_str());
+    }
 
     for data_class in data_classes {
         create_data_class(dir, &data_class, package_name.as_str());
@@ -141,6 +158,147 @@ fn main() {
     // println!("Abs path of file is: {abs_path:?}");
 }
 
+/// Collects the metadata blocks (the kind of item followed by its JSON description) that the macros
+/// write while the crate is checked. A new directory is used on every run, and the cfg pointing to it
+/// makes cargo check the crate again even if it's up to date.
+///
+/// Bindings require `jni_init!`, whose block is always written, so a crate using kotars without any block
+/// was expanded by macros too old to write them and `None` is returned. Crates without kotars have no blocks.
+fn metadata_records() -> Option<Vec<String>> {
+    let cargo_metadata = cargo_metadata();
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time is before the UNIX epoch")
+        .as_nanos();
+    let metadata_dir = cargo_target_dir(&cargo_metadata)
+        .join("kotars")
+        .join(format!("{}-{nonce}", std::process::id()));
+
+    std::fs::create_dir_all(&metadata_dir).expect("Creating kotars metadata directory failed");
+
+    let status = Command::new("cargo")
+        .args(["rustc", "--profile", "check", "--", "--cfg"])
+        .arg(format!("kotars_metadata=\"{nonce}\""))
+        .env(KOTARS_METADATA_DIR_ENV, &metadata_dir)
+        .status()
+        .expect("Running cargo check failed");
+
+    if !status.success() {
+        std::fs::remove_dir_all(&metadata_dir).expect("Removing kotars metadata directory failed");
+        panic!("cargo check failed with {status}");
+    }
+
+    let mut files = std::fs::read_dir(&metadata_dir)
+        .expect("Reading kotars metadata directory failed")
+        .map(|entry| entry.expect("Reading kotars metadata directory failed").path())
+        .collect::<Vec<PathBuf>>();
+    // File names keep the order in which the macros were expanded
+    files.sort();
+
+    let records = files
+        .iter()
+        .map(|file| std::fs::read_to_string(file).unwrap_or_else(|err| panic!("Reading kotars metadata {file:?} failed. {err}")))
+        .collect::<Vec<String>>();
+
+    std::fs::remove_dir_all(&metadata_dir).expect("Removing kotars metadata directory failed");
+
+    if records.is_empty() && depends_on_kotars(&cargo_metadata) {
+        return None;
+    }
+
+    Some(records)
+}
+
+/// Reads the metadata blocks from the linker section the macros embed in the library.
+fn library_records(library: &Path) -> Vec<String> {
+    let data = std::fs::read(library).unwrap_or_else(|err| panic!("Reading library {library:?} failed. {err}"));
+    let file = object::File::parse(data.as_slice()).unwrap_or_else(|err| panic!("Unable to parse library {library:?}. {err}"));
+
+    let section = [METADATA_SECTION_ELF, METADATA_SECTION_MACHO, METADATA_SECTION_PE]
+        .iter()
+        .find_map(|name| file.section_by_name(name))
+        .unwrap_or_else(|| panic!("No kotars metadata found in {library:?}, it must be built with kotars bindings"));
+    let section_data = section.data().unwrap_or_else(|err| panic!("Reading kotars metadata of {library:?} failed. {err}"));
+
+    // Linkers may pad the blocks, which results in empty ones
+    let mut records = section_data
+        .split(|byte| *byte == 0)
+        .filter(|record| !record.is_empty())
+        .map(|record| String::from_utf8(record.to_vec()).unwrap_or_else(|err| panic!("Invalid kotars metadata record in {library:?}. {err}")))
+        .collect::<Vec<String>>();
+
+    // Linkers place the blocks in any order, so they are sorted to generate the same Kotlin code on every build
+    records.sort_by_cached_key(|record| {
+        let mut lines = record.lines();
+        let item = lines.next().unwrap_or_default().split_whitespace().nth(2).unwrap_or_default().to_string();
+        let kind = lines.next().unwrap_or_default().split(' ').next().unwrap_or_default().to_string();
+
+        (kind, item, record.clone())
+    });
+
+    records
+}
+
+fn cargo_metadata() -> serde_json::Value {
+    let output = Command::new("cargo")
+        .args(["metadata", "--format-version", "1", "--no-deps"])
+        .output()
+        .expect("Running cargo metadata failed");
+
+    serde_json::from_slice(&output.stdout).expect("Unable to deserialize cargo metadata")
+}
+
+fn cargo_target_dir(cargo_metadata: &serde_json::Value) -> PathBuf {
+    cargo_metadata["target_directory"]
+        .as_str()
+        .map(PathBuf::from)
+        .expect("Target directory not found in cargo metadata")
+}
+
+/// Whether the package checked by cargo, the one whose manifest is the closest to the current directory,
+/// depends on kotars
+fn depends_on_kotars(cargo_metadata: &serde_json::Value) -> bool {
+    let current_dir = std::env::current_dir().expect("Reading the current directory failed");
+
+    cargo_metadata["packages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|package| {
+            let package_dir = Path::new(package["manifest_path"].as_str()?).parent()?;
+            current_dir.starts_with(package_dir).then_some((package_dir.components().count(), package))
+        })
+        .max_by_key(|(depth, _)| *depth)
+        .is_some_and(|(_, package)| {
+            package["dependencies"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .any(|dependency| dependency["name"] == "kotars")
+        })
+}
+
+/// Extracts the metadata blocks from the header comments of the expanded source code.
+fn expanded_source_records() -> Vec<String> {
+    let mut command = Command::new("cargo");
+    command.arg("rustc");
+    command.arg("--profile");
+    command.arg("check");
+    command.arg("--");
+    command.arg("-Zunpretty=expanded");
+
+    let res = command.output().expect("Output read failed");
+    let out_text = String::from_utf8_lossy(&res.stdout);
+    let err = String::from_utf8_lossy(&res.stderr);
+    let text = out_text.to_string();
+
+    if !err.is_empty() && text.is_empty() {
+        panic!("{err}")
+    }
+
+    metadata::doc_strings(&text)
+}
+
 fn create_interface(dir: &Path, interface: &RsInterface, package_name: &str) {
     let interface_name = &interface.name;
     let file_name = format!("{interface_name}.kt");
@@ -165,8 +323,9 @@ fn create_interface(dir: &Path, interface: &RsInterface, package_name: &str) {
         .collect::<Vec<String>>()
         .join("\n");
 
+    let package_declaration = package_declaration(package_name);
     let content = format!(r#"
-//package {package_name}
+{package_declaration}
 
 interface {interface_name} {{
 
@@ -178,18 +337,51 @@ interface {interface_name} {{
     file.flush().unwrap();
 }
 
-fn create_base_files(dir: &Path) {
-    let content = include_str!("AutoCloseThread.kt");
+fn package_declaration(package_name: &str) -> String {
+    if package_name.is_empty() {
+        String::new()
+    } else {
+        format!("package {package_name}")
+    }
+}
+
+fn create_base_files(dir: &Path, package_name: &str, has_async_functions: bool) {
+    let package_declaration = package_declaration(package_name);
+
+    let content = format!("{package_declaration}\n\n{}", include_str!("AutoCloseThread.kt"));
     let file = dir.join("AutoCloseThread.kt");
     let file = file.as_path();
     let mut file = File::create(file).expect("Creating AutoCloseThread.kt failed.");
 
     file.write_all(content.as_bytes()).expect("Writing to AutoCloseThread.kt failed.");
+
+    let content = format!("{package_declaration}\n\n{}", include_str!("RustException.kt"));
+    let file = dir.join("RustException.kt");
+    let mut file = File::create(file).expect("Creating RustException.kt failed.");
+
+    file.write_all(content.as_bytes()).expect("Writing to RustException.kt failed.");
+
+    // RustTask.kt depends on kotlinx.coroutines, which is only required from the crates with async functions
+    let file = dir.join("RustTask.kt");
+    if has_async_functions {
+        let content = format!("{package_declaration}\n\n{}", include_str!("RustTask.kt"));
+        let mut file = File::create(file).expect("Creating RustTask.kt failed.");
+
+        file.write_all(content.as_bytes()).expect("Writing to RustTask.kt failed.");
+    } else if file.exists() {
+        std::fs::remove_file(file).expect("Removing the stale RustTask.kt failed.");
+    }
+
+    let content = format!("{package_declaration}\n\n{}", include_str!("RustBuffer.kt"));
+    let file = dir.join("RustBuffer.kt");
+    let mut file = File::create(file).expect("Creating RustBuffer.kt failed.");
+
+    file.write_all(content.as_bytes()).expect("Writing to RustBuffer.kt failed.");
 }
 
-fn create_class(dir: &Path, rs_struct: RsStruct, package_name: &str, functions: Vec<&Function>) {
+fn create_class(dir: &Path, rs_class: RsClass, package_name: &str, functions: Vec<&Function>) {
     println!("Dir is {dir:?}");
-    let class_name = &rs_struct.name;
+    let class_name = &rs_class.name;
     let file_name = format!("{class_name}.kt");
     let file_path = Path::new(file_name.as_str());
     let file_path = PathBuf::from(dir).join(file_path);
@@ -234,9 +426,15 @@ fn create_class(dir: &Path, rs_struct: RsStruct, package_name: &str, functions:
         .collect::<Vec<String>>()
         .join("\n");
 
+    let thread_safety = class_thread_safety(rs_class.sync);
+
+    let package_declaration = package_declaration(package_name);
     let content = format!(r#"
-//package {package_name}
+{package_declaration}
 
+/**
+ * {thread_safety}
+ */
 class {class_name} private constructor(val pointer: Long) : AutoCloseable {{
     private val resource: NativeResource = thread.addObject(this, pointer, "{class_name}") {{ {class_name}Obj.destroy(it) }}
 
@@ -267,24 +465,47 @@ private object {class_name}Obj {{
     file.flush().unwrap();
 }
 
+fn class_thread_safety(sync: ClassSync) -> String {
+    let thread_safety = match sync {
+        ClassSync::None => "Not thread-safe: its functions must not be called from several threads at the same time.",
+        ClassSync::Mutex => "Thread-safe: calls to its functions from several threads run one at a time.\n * Calling functions of the same instance from callbacks run by its functions blocks forever.",
+        ClassSync::RwLock => "Thread-safe: functions reading it run at the same time, functions modifying it run alone.\n * Calling functions of the same instance from callbacks run by its functions may block forever.",
+    };
+
+    format!("{thread_safety}\n * Passing the same instance to a function more than once, as receiver or parameter, throws an\n * [IllegalArgumentException] if any of them is modified.")
+}
+
 fn format_function(func: &Function) -> String {
     let name = string_to_camel_case(&func.name);
 
-    let mut parameters_formatted = format_func_parameters(&func.parameters, true);
-    let return_ty = formatted_return_ty(&func.return_type);
+    let mut parameters_formatted = format_func_parameters(&func.parameters, true, true);
+    let return_ty = if func.is_async {
+        let future_ty = async_future_type(&func.return_type);
+        parameters_formatted = format!("{parameters_formatted}\n        future: {future_ty},");
+        ": Long".to_string()
+    } else {
+        formatted_return_ty(&func.return_type, true)
+    };
     if !parameters_formatted.is_empty() && !parameters_formatted.ends_with('\n') {
         parameters_formatted = format!("\n        {parameters_formatted}\n    ");
     };
 
-    format!("    external fun {name}({parameters_formatted}){return_ty}")
+    // Errors of async functions complete the future exceptionally instead of being thrown
+    let throws_annotation = if func.is_async {
+        String::new()
+    } else {
+        formatted_throws_annotation(&func.return_type, "    ")
+    };
+
+    format!("{throws_annotation}    external fun {name}({parameters_formatted}){return_ty}")
 }
 
 fn format_function_mapping(func: &Function, is_static: bool) -> String {
     let struct_name = &func.owner_name;
     let name = string_to_camel_case(&func.name);
 
-    let mut parameters_formatted = format_func_parameters(&func.parameters, is_static);
-    let return_ty = formatted_return_ty(&func.return_type);
+    let mut parameters_formatted = format_func_parameters(&func.parameters, is_static, false);
+    let return_ty = formatted_return_ty(&func.return_type, false);
     if !parameters_formatted.is_empty() && !parameters_formatted.ends_with('\n') {
         parameters_formatted = format!("\n        {parameters_formatted}\n    ");
     };
@@ -292,34 +513,101 @@ fn format_function_mapping(func: &Function, is_static: bool) -> String {
     let params_as_args = func.parameters.iter()
         .map(|param| {
             match param {
-                Parameter::Typed { name, .. } => name,
-                Parameter::Receiver { .. } => "this.pointer"
+                Parameter::Typed { name, ty, .. } => kotlin_to_jni_value(ty, name),
+                Parameter::Receiver { .. } => "this.pointer".to_string()
             }
         })
-        .collect::<Vec<&str>>()
+        .collect::<Vec<String>>()
         .join(", ");
 
+    let throws_annotation = formatted_throws_annotation(&func.return_type, "    ");
+
+    if func.is_async {
+        let future_ty = async_future_type(&func.return_type);
+        let params_as_args = if params_as_args.is_empty() {
+            "future".to_string()
+        } else {
+            format!("{params_as_args}, future")
+        };
+        let return_statement = if return_ty.is_empty() { "" } else { "return " };
+
+        return format!(
+            r#"
+    /**
+     * Runs the Rust future on the pool of worker threads of kotars, which has no reactor: it must not rely on the one
+     * of a runtime like tokio or async-std, whose futures have to be spawned on their runtime and their join handle
+     * awaited instead. Cancelling the coroutine drops the future.
+     */
+{throws_annotation}    suspend fun {name}({parameters_formatted}){return_ty} {{
+        val future = {future_ty}()
+        {return_statement}awaitRustTask({struct_name}Obj.{name}({params_as_args}), future)
+    }}
+    "#);
+    }
+
+    let function_call = format!("{struct_name}Obj.{name}({params_as_args})");
+    let function_call = match &func.return_type {
+        Some(ty) => jni_to_kotlin_value(ty, &function_call),
+        None => function_call,
+    };
+
     format!(
         r#"
-    fun {name}({parameters_formatted}){return_ty} =
-        {struct_name}Obj.{name}({params_as_args})
+{throws_annotation}    fun {name}({parameters_formatted}){return_ty} =
+        {function_call}
     "#)
 }
 
 fn format_interface_function(func: &Function) -> String {
     let name = string_to_camel_case(&func.name);
 
-    let mut parameters_formatted = format_func_parameters(&func.parameters, false);
-    let return_ty = formatted_return_ty(&func.return_type);
+    let mut parameters_formatted = format_func_parameters(&func.parameters, false, false);
+    let return_ty = formatted_return_ty(&func.return_type, false);
 
     if !parameters_formatted.is_empty() && !parameters_formatted.ends_with('\n') {
         parameters_formatted = format!("\n        {parameters_formatted}\n    ");
     };
 
-    format!("fun {name}({parameters_formatted}){return_ty}")
+    let interface_function = format!("fun {name}({parameters_formatted}){return_ty}");
+
+    if func.has_unsigned_types() {
+        format!("{interface_function}\n\n{}", format_interface_jni_function(func))
+    } else {
+        interface_function
+    }
+}
+
+fn format_interface_jni_function(func: &Function) -> String {
+    let name = string_to_camel_case(&func.name);
+    let jni_name = func.interface_jni_method_name();
+
+    let mut parameters_formatted = format_func_parameters(&func.parameters, false, true);
+    let return_ty = formatted_return_ty(&func.return_type, true);
+
+    if !parameters_formatted.is_empty() && !parameters_formatted.ends_with('\n') {
+        parameters_formatted = format!("\n        {parameters_formatted}\n    ");
+    };
+
+    let params_as_args = func.parameters.iter()
+        .filter_map(|param| {
+            match param {
+                Parameter::Typed { name, ty, .. } => Some(jni_to_kotlin_value(ty, name)),
+                Parameter::Receiver { .. } => None,
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    let function_call = format!("{name}({params_as_args})");
+    let function_call = match &func.return_type {
+        Some(ty) => kotlin_to_jni_value(ty, &function_call),
+        None => function_call,
+    };
+
+    format!("fun {jni_name}({parameters_formatted}){return_ty} =\n        {function_call}")
 }
 
-fn create_data_class(dir: &Path, rs_struct: &RsStruct, _package_name: &str) {
+fn create_data_class(dir: &Path, rs_struct: &RsStruct, package_name: &str) {
     let class_name = &rs_struct.name;
     let file_name = format!("{class_name}.kt");
 
@@ -328,45 +616,178 @@ fn create_data_class(dir: &Path, rs_struct: &RsStruct, _package_name: &str) {
 
     let mut file = File::create(file_path).expect("File creation failed");
 
-    let fields = rs_struct.fields
+    let fields = format_data_class_fields(rs_struct, "\n    ");
+
+    let package_declaration = package_declaration(package_name);
+    let content = format!(r#"
+{package_declaration}
+
+data class {class_name} (
+    {fields}
+)
+"#);
+
+    file.write_all(content.as_bytes()).expect("Writing Kotlin source code failed");
+    file.flush().unwrap();
+}
+
+fn create_tuple_class(dir: &Path, arity: usize, package_name: &str) {
+    let class_name = tuple_class_name(arity);
+    let file_name = format!("{class_name}.kt");
+
+    let file_path = Path::new(file_name.as_str());
+    let file_path = PathBuf::from(dir).join(file_path);
+
+    let mut file = File::create(file_path).expect("File creation failed");
+
+    let type_parameters = (1..=arity)
+        .map(|index| format!("out T{index}"))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    let fields = (1..=arity)
+        .map(|index| format!("val value{index}: T{index},"))
+        .collect::<Vec<String>>()
+        .join("\n    ");
+
+    let package_declaration = package_declaration(package_name);
+    let content = format!(r#"
+{package_declaration}
+
+data class {class_name}<{type_parameters}>(
+    {fields}
+)
+"#);
+
+    file.write_all(content.as_bytes()).expect("Writing Kotlin source code failed");
+    file.flush().unwrap();
+}
+
+fn create_enum_class(dir: &Path, rs_enum: &RsEnum, package_name: &str) {
+    let enum_name = &rs_enum.name;
+    let file_name = format!("{enum_name}.kt");
+
+    let file_path = Path::new(file_name.as_str());
+    let file_path = PathBuf::from(dir).join(file_path);
+
+    let mut file = File::create(file_path).expect("File creation failed");
+
+    let variants = rs_enum.variants
+        .iter()
+        .map(|variant| format!("{variant},"))
+        .collect::<Vec<String>>()
+        .join("\n    ");
+
+    let package_declaration = package_declaration(package_name);
+    let content = format!(r#"
+{package_declaration}
+
+enum class {enum_name} {{
+    {variants}
+}}
+"#);
+
+    file.write_all(content.as_bytes()).expect("Writing Kotlin source code failed");
+    file.flush().unwrap();
+}
+
+fn format_data_class_fields(rs_struct: &RsStruct, separator: &str) -> String {
+    rs_struct.fields
         .iter()
         .enumerate()
         .map(|(index, field)| {
-            let alternative_name = format!("param{index}");
-            let field_name = string_to_camel_case(field.name.as_ref().unwrap_or(&alternative_name));
+            let field_name = string_to_camel_case(&field.safe_name(&index));
             let ty = jni_to_kotlin_type(&field.ty, false);
             format!("val {field_name}: {ty},")
         })
         .collect::<Vec<String>>()
-        .join("\n    ");
+        .join(separator)
+}
+
+fn create_sealed_class(dir: &Path, rs_sealed_class: &RsSealedClass, package_name: &str) {
+    let sealed_class_name = &rs_sealed_class.name;
+    let file_name = format!("{sealed_class_name}.kt");
+
+    let file_path = Path::new(file_name.as_str());
+    let file_path = PathBuf::from(dir).join(file_path);
+
+    let mut file = File::create(file_path).expect("File creation failed");
+
+    let variants = rs_sealed_class.variants
+        .iter()
+        .map(|variant| {
+            let variant_name = &variant.name;
+
+            if variant.fields.is_empty() {
+                format!("object {variant_name} : {sealed_class_name}()")
+            } else {
+                let fields = format_data_class_fields(variant, "\n        ");
+                format!(r#"data class {variant_name}(
+        {fields}
+    ) : {sealed_class_name}()"#)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n\n    ");
 
-    // TODO Eventually add package name
+    let package_declaration = package_declaration(package_name);
     let content = format!(r#"
-data class {class_name} (
-    {fields}
-)
+{package_declaration}
+
+sealed class {sealed_class_name} {{
+    {variants}
+}}
 "#);
 
     file.write_all(content.as_bytes()).expect("Writing Kotlin source code failed");
     file.flush().unwrap();
 }
 
-fn formatted_return_ty(return_ty: &Option<JniType>) -> String {
+fn formatted_throws_annotation(return_ty: &Option<JniType>, indentation: &str) -> String {
+    if let Some(JniType::Result(_)) = return_ty {
+        format!("{indentation}@Throws(RustException::class)\n")
+    } else {
+        String::new()
+    }
+}
+
+// Async functions complete the future with the boxed value of their Kotlin type, nullable for Unit
+// as Rust completes it with null.
+fn async_future_type(return_ty: &Option<JniType>) -> String {
+    let value_ty = match return_ty.as_ref() {
+        None | Some(JniType::Void) => "Unit?".to_string(),
+        Some(JniType::Result(ty)) if matches!(ty.as_ref(), JniType::Void) => "Unit?".to_string(),
+        Some(ty) => jni_to_kotlin_type(ty, false),
+    };
+
+    format!("java.util.concurrent.CompletableFuture<{value_ty}>")
+}
+
+fn formatted_return_ty(return_ty: &Option<JniType>, is_jni_signature: bool) -> String {
     match return_ty.as_ref() {
-        None => { String::new() }
+        None | Some(JniType::Void) => { String::new() }
+        Some(JniType::Result(ty)) if matches!(ty.as_ref(), JniType::Void) => { String::new() }
         Some(ty) => {
-            let ty = jni_to_kotlin_type(ty, false);
+            let ty = if is_jni_signature {
+                jni_to_kotlin_jni_type(ty)
+            } else {
+                jni_to_kotlin_type(ty, false)
+            };
             format!(": {ty}")
         }
     }
 }
 
-fn format_func_parameters(params: &[Parameter], is_static: bool) -> String {
+fn format_func_parameters(params: &[Parameter], is_static: bool, is_jni_signature: bool) -> String {
     params.iter()
         .map(|param| {
             match param {
-                Parameter::Typed { name, ty, is_borrow, is_mutable } => {
-                    let kotlin_ty = jni_to_kotlin_type(ty, false);
+                Parameter::Typed { name, ty, .. } => {
+                    let kotlin_ty = if is_jni_signature {
+                        jni_to_kotlin_jni_type(ty)
+                    } else {
+                        jni_to_kotlin_type(ty, false)
+                    };
                     format!("{name}: {kotlin_ty},")
                 }
                 Parameter::Receiver { .. } => { if is_static { "pointer: Long,".to_string() } else { String::new() } }
@@ -379,23 +800,53 @@ fn format_func_parameters(params: &[Parameter], is_static: bool) -> String {
 
 fn jni_to_kotlin_type(ty: &JniType, is_nullable: bool) -> String {
     let ty = match ty {
+        JniType::Int8 => "Byte".to_string(),
+        JniType::Int16 => "Short".to_string(),
         JniType::Int32 => "Int".to_string(),
-        JniType::Int64 => "Long".to_string(),
-        JniType::UInt64 => "Long".to_string(), // TODO This should be unsigned, perhaps use an object?
+        JniType::Int64 | JniType::ISize | JniType::USize => "Long".to_string(),
+        JniType::UInt8 => "UByte".to_string(),
+        JniType::UInt16 => "UShort".to_string(),
+        JniType::UInt32 => "UInt".to_string(),
+        JniType::UInt64 => "ULong".to_string(),
         JniType::Float32 => "Float".to_string(),
         JniType::Float64 => "Double".to_string(),
         JniType::String => "String".to_string(),
         JniType::Boolean => "Boolean".to_string(),
+        // Code point of the char, as a Kotlin `Char` can only hold the ones of the Basic Multilingual Plane
+        JniType::Char => "Int".to_string(),
         JniType::ByteArray => "ByteArray".to_string(),
-        JniType::CustomType(name) => name.clone(),
+        JniType::PrimitiveArray(ty) => format!("{}Array", jni_to_kotlin_type(ty, false)),
+        JniType::DirectBuffer => "java.nio.ByteBuffer".to_string(),
+        JniType::RustBuffer => "RustBuffer".to_string(),
+        JniType::CustomType(name) | JniType::Enum(name) => name.clone(),
         JniType::Receiver(_) => todo!(),
         JniType::Interface(name) => name.clone(),
         JniType::Void => "Unit".to_string(),
         JniType::Option(ty) => jni_to_kotlin_type(ty, true),
+        JniType::Result(ty) => jni_to_kotlin_type(ty, false),
         JniType::Vec(ty) => {
             let ty_name = jni_to_kotlin_type(ty, false);
             format!("Array<{ty_name}>")
         }
+        JniType::Tuple(element_types) => {
+            let class_name = tuple_class_name(element_types.len());
+            let element_types = element_types
+                .iter()
+                .map(|ty| jni_to_kotlin_type(ty, false))
+                .collect::<Vec<String>>()
+                .join(", ");
+
+            format!("{class_name}<{element_types}>")
+        }
+        JniType::HashSet(ty) | JniType::BTreeSet(ty) => {
+            let ty_name = jni_to_kotlin_type(ty, false);
+            format!("Set<{ty_name}>")
+        }
+        JniType::HashMap(key_ty, value_ty) | JniType::BTreeMap(key_ty, value_ty) => {
+            let key_ty_name = jni_to_kotlin_type(key_ty, false);
+            let value_ty_name = jni_to_kotlin_type(value_ty, false);
+            format!("Map<{key_ty_name}, {value_ty_name}>")
+        }
     };
 
     if is_nullable {
@@ -404,3 +855,41 @@ fn jni_to_kotlin_type(ty: &JniType, is_nullable: bool) -> String {
         ty
     }
 }
+
+// Kotlin mangles the JVM names of functions using unsigned types, so JNI functions use the signed
+// types of the same width and the wrappers reinterpret the bits in both directions. Nullable
+// unsigned types are passed as `Any?`, which keeps the boxed value untouched.
+fn jni_to_kotlin_jni_type(ty: &JniType) -> String {
+    match ty {
+        JniType::UInt8 => "Byte".to_string(),
+        JniType::UInt16 => "Short".to_string(),
+        JniType::UInt32 => "Int".to_string(),
+        JniType::UInt64 => "Long".to_string(),
+        JniType::Option(ty) if ty.is_unsigned() => "Any?".to_string(),
+        JniType::Result(ty) => jni_to_kotlin_jni_type(ty),
+        ty => jni_to_kotlin_type(ty, false),
+    }
+}
+
+fn kotlin_to_jni_value(ty: &JniType, value: &str) -> String {
+    match ty {
+        JniType::UInt8 => format!("{value}.toByte()"),
+        JniType::UInt16 => format!("{value}.toShort()"),
+        JniType::UInt32 => format!("{value}.toInt()"),
+        JniType::UInt64 => format!("{value}.toLong()"),
+        JniType::Result(ty) => kotlin_to_jni_value(ty, value),
+        _ => value.to_string(),
+    }
+}
+
+fn jni_to_kotlin_value(ty: &JniType, value: &str) -> String {
+    match ty {
+        JniType::UInt8 => format!("{value}.toUByte()"),
+        JniType::UInt16 => format!("{value}.toUShort()"),
+        JniType::UInt32 => format!("{value}.toUInt()"),
+        JniType::UInt64 => format!("{value}.toULong()"),
+        JniType::Option(ty) if ty.is_unsigned() => format!("({value} as {}?)", jni_to_kotlin_type(ty, false)),
+        JniType::Result(ty) => jni_to_kotlin_value(ty, value),
+        _ => value.to_string(),
+    }
+}
@@ -0,0 +1,301 @@
+use std::fmt::{Display, Formatter};
+
+use kotars_common::{BINDING_END, BINDING_START, BINDING_VERSION, Function, RsClass, RsEnum, RsInterface, RsSealedClass, RsStruct};
+use serde::de::DeserializeOwned;
+
+pub enum Binding {
+    PackageName(String),
+    Enum(RsEnum),
+    Function(Function),
+    Class(RsClass),
+    DataClass(RsStruct),
+    SealedClass(RsSealedClass),
+    Interface(RsInterface),
+}
+
+pub struct BindingError {
+    /// Rust item the malformed block comes from, if the block header could be read
+    item: Option<String>,
+    message: String,
+}
+
+impl Display for BindingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.item {
+            Some(item) => write!(f, "malformed binding of `{item}`: {}", self.message),
+            None => write!(f, "malformed binding: {}", self.message),
+        }
+    }
+}
+
+/// Parses the `JNI_BINDING_START … JNI_BINDING_END` blocks found in the given lines, ignoring any other line.
+/// Every malformed block is reported instead of stopping at the first one.
+pub fn parse_bindings<'a>(lines: impl IntoIterator<Item = &'a str>) -> Result<Vec<Binding>, Vec<BindingError>> {
+    let mut bindings = Vec::new();
+    let mut errors = Vec::new();
+    let mut lines = lines.into_iter().map(str::trim).peekable();
+
+    while let Some(line) = lines.next() {
+        let Some(header) = line.strip_prefix(BINDING_START) else {
+            continue;
+        };
+
+        let (version, item) = parse_header(header);
+        let item = item.map(str::to_string);
+
+        let mut content = Vec::new();
+        let mut is_terminated = false;
+
+        while let Some(line) = lines.peek() {
+            if line.starts_with(BINDING_START) {
+                break;
+            }
+
+            let line = lines.next().unwrap();
+            if line == BINDING_END {
+                is_terminated = true;
+                break;
+            }
+
+            content.push(line);
+        }
+
+        let binding = if !is_terminated {
+            Err(format!("missing {BINDING_END}"))
+        } else {
+            version.and_then(|version| {
+                if version != BINDING_VERSION {
+                    Err(format!("generated with metadata version {version}, but this cargo-kotars reads version {BINDING_VERSION}. Use the same version of kotars and cargo-kotars"))
+                } else {
+                    match content.as_slice() {
+                        [record] => parse_record(record),
+                        _ => Err(format!("expected a single record, found {}", content.len())),
+                    }
+                }
+            })
+        };
+
+        match binding {
+            Ok(binding) => bindings.push(binding),
+            Err(message) => errors.push(BindingError { item, message }),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(bindings)
+    } else {
+        Err(errors)
+    }
+}
+
+fn parse_header(header: &str) -> (Result<u32, String>, Option<&str>) {
+    let mut parts = header.trim().splitn(2, ' ');
+    let version = parts.next().unwrap_or_default();
+    let item = parts.next().map(str::trim).filter(|item| !item.is_empty());
+
+    let version = version
+        .strip_prefix('v')
+        .and_then(|version| version.parse::<u32>().ok())
+        .ok_or_else(|| format!("missing metadata version in header `{BINDING_START}{header}`, it was generated by an older version of kotars"));
+
+    (version, item)
+}
+
+fn parse_record(record: &str) -> Result<Binding, String> {
+    let (kind, json) = record
+        .split_once(' ')
+        .ok_or_else(|| format!("record without content `{record}`"))?;
+
+    let binding = match kind {
+        "JNI_PACKAGE_NAME" => Binding::PackageName(deserialize(kind, json)?),
+        "JNI_ENUM" => Binding::Enum(deserialize(kind, json)?),
+        "JNI_FN_DATA" => Binding::Function(deserialize(kind, json)?),
+        "JNI_CLASS" => Binding::Class(deserialize(kind, json)?),
+        "JNI_DATA_CLASS" => Binding::DataClass(deserialize(kind, json)?),
+        "JNI_SEALED_CLASS" => Binding::SealedClass(deserialize(kind, json)?),
+        "JNI_INTERFACE" => Binding::Interface(deserialize(kind, json)?),
+        kind => return Err(format!("unknown record kind {kind}")),
+    };
+
+    Ok(binding)
+}
+
+fn deserialize<T: DeserializeOwned>(kind: &str, json: &str) -> Result<T, String> {
+    serde_json::from_str(json).map_err(|err| format!("unable to deserialize {kind}: {err}"))
+}
+
+/// Values of the `#[doc = "…"]` attributes of the expanded source code, in the order they appear.
+pub fn doc_strings(source: &str) -> Vec<String> {
+    let mut doc_strings = Vec::new();
+    let mut rest = source;
+
+    while let Some(start) = rest.find("#[doc") {
+        rest = &rest[start + "#[doc".len()..];
+
+        let Some(value) = rest.trim_start().strip_prefix('=') else {
+            continue;
+        };
+
+        if let Some((doc_string, remaining)) = parse_string_literal(value.trim_start()) {
+            doc_strings.push(doc_string);
+            rest = remaining;
+        }
+    }
+
+    doc_strings
+}
+
+/// Parses a Rust string literal at the start of `source`, returning its value and the remaining source.
+fn parse_string_literal(source: &str) -> Option<(String, &str)> {
+    if let Some(raw) = source.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let raw = raw[hashes..].strip_prefix('"')?;
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        let end = raw.find(&terminator)?;
+
+        return Some((raw[..end].to_string(), &raw[end + terminator.len()..]));
+    }
+
+    let mut chars = source.strip_prefix('"')?.char_indices();
+    let body = &source[1..];
+    let mut value = String::new();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &body[index + 1..])),
+            '\\' => {
+                let (_, escaped) = chars.next()?;
+
+                match escaped {
+                    'n' => value.push('\n'),
+                    'r' => value.push('\r'),
+                    't' => value.push('\t'),
+                    '0' => value.push('\0'),
+                    '\\' | '"' | '\'' => value.push(escaped),
+                    'x' => {
+                        let digits = [chars.next()?.1, chars.next()?.1].iter().collect::<String>();
+                        value.push(u8::from_str_radix(&digits, 16).ok()? as char);
+                    }
+                    'u' => {
+                        let (_, '{') = chars.next()? else {
+                            return None;
+                        };
+                        let mut digits = String::new();
+
+                        loop {
+                            match chars.next()?.1 {
+                                '}' => break,
+                                '_' => {}
+                                digit => digits.push(digit),
+                            }
+                        }
+
+                        value.push(char::from_u32(u32::from_str_radix(&digits, 16).ok()?)?);
+                    }
+                    // Line continuation, skips the line break and the indentation of the next line
+                    '\n' => {
+                        while chars.clone().next().is_some_and(|(_, c)| c.is_whitespace()) {
+                            chars.next();
+                        }
+                    }
+                    _ => return None,
+                }
+            }
+            c => value.push(c),
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use kotars_common::binding_block_lines;
+
+    use super::*;
+
+    fn parse_lines(lines: &[String]) -> Result<Vec<Binding>, Vec<BindingError>> {
+        parse_bindings(lines.iter().map(String::as_str))
+    }
+
+    #[test]
+    fn parses_escapes() {
+        let source = r#""quote \" \' backslash \\ lines \n \r \t nul \0 hex \x41 unicode \u{1F600} \u{1_f6_00}" rest"#;
+
+        let (value, rest) = parse_string_literal(source).unwrap();
+
+        assert_eq!(value, "quote \" ' backslash \\ lines \n \r \t nul \0 hex A unicode \u{1F600} \u{1F600}");
+        assert_eq!(rest, " rest");
+    }
+
+    #[test]
+    fn parses_line_continuations() {
+        let (value, rest) = parse_string_literal("\"first \\\n        second\"").unwrap();
+
+        assert_eq!(value, "first second");
+        assert_eq!(rest, "");
+    }
+
+    #[test]
+    fn parses_raw_strings() {
+        assert_eq!(parse_string_literal(r#"r"plain \n" rest"#), Some((r"plain \n".to_string(), " rest")));
+        assert_eq!(
+            parse_string_literal(r###"r#"with "quotes" and \"# rest"###),
+            Some((r#"with "quotes" and \"#.to_string(), " rest")),
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_literals() {
+        assert_eq!(parse_string_literal(r#""unterminated"#), None);
+        assert_eq!(parse_string_literal(r#""trailing backslash \"#), None);
+        assert_eq!(parse_string_literal(r#""short hex \x4"#), None);
+        assert_eq!(parse_string_literal(r#""open unicode \u{41"#), None);
+        assert_eq!(parse_string_literal(r###"r#"missing hash""###), None);
+        assert_eq!(parse_string_literal("not a literal"), None);
+    }
+
+    #[test]
+    fn parses_multiple_records() {
+        let lines = [
+            binding_block_lines("jni_init", r#"JNI_PACKAGE_NAME "com.example""#),
+            binding_block_lines("Counter", r#"JNI_CLASS {"name":"Counter"}"#),
+        ]
+        .concat();
+
+        let bindings = parse_lines(&lines).unwrap_or_else(|_| panic!("Parsing the bindings failed"));
+
+        assert!(matches!(bindings.as_slice(), [Binding::PackageName(package), Binding::Class(class)] if package == "com.example" && class.name == "Counter"));
+    }
+
+    #[test]
+    fn reports_unterminated_blocks() {
+        let mut lines = binding_block_lines("Counter", r#"JNI_CLASS {"name":"Counter"}"#).to_vec();
+        lines.pop();
+        lines.extend(binding_block_lines("jni_init", r#"JNI_PACKAGE_NAME "com.example""#));
+
+        let Err(errors) = parse_lines(&lines) else {
+            panic!("Unterminated block was parsed");
+        };
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].item.as_deref(), Some("Counter"));
+        assert_eq!(errors[0].message, format!("missing {BINDING_END}"));
+    }
+
+    #[test]
+    fn reports_version_mismatches() {
+        let lines = [
+            format!("{BINDING_START} v{} Counter", BINDING_VERSION + 1),
+            r#"JNI_CLASS {"name":"Counter"}"#.to_string(),
+            BINDING_END.to_string(),
+        ];
+
+        let Err(errors) = parse_lines(&lines) else {
+            panic!("Block of another version was parsed");
+        };
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.starts_with(&format!("generated with metadata version {}", BINDING_VERSION + 1)));
+    }
+}
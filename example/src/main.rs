@@ -1,7 +1,8 @@
-use kotars::{jni_class, jni_data_class, jni_init, jni_interface, jni_struct_impl};
-use notify::{Config, Error, ErrorKind, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
-jni_init!("");
+use kotars::{jni_class, jni_data_class, jni_enum, jni_init, jni_interface, jni_sealed_class, jni_struct_impl};
+
+jni_init!("com.jetpackduba.kotars.example");
 
 fn main() {
     println!("Hello, world!");
@@ -13,6 +14,51 @@ fn main() {
 #[jni_class]
 struct FileWatcher {}
 
+#[jni_class(sync = "mutex")]
+struct WatchStats {
+    events: u64,
+    last_path: Option<String>,
+}
+
+#[jni_enum]
+#[derive(Clone)]
+enum WatchMode {
+    Recursive,
+    NonRecursive,
+}
+
+#[jni_data_class]
+#[derive(Clone)]
+struct WatchOptions {
+    path: String,
+    mode: WatchMode,
+    excluded_paths: Vec<String>,
+    labels: HashMap<String, String>,
+    max_depth: Option<i64>,
+}
+
+#[jni_sealed_class]
+enum WatchEvent {
+    Created { path: String },
+    Renamed(String, String),
+    Deleted,
+}
+
+#[jni_interface(persistent)]
+trait WatchListener {
+    fn on_changes(&self, changes_count: u32) -> u8;
+    fn on_error(&self, message: Option<String>, code: Option<u32>) -> Option<bool>;
+    fn options_for(&self, path: String, mode: &WatchMode) -> WatchOptions;
+    fn ignored(&self, options: &WatchOptions) -> Vec<String>;
+    fn contents(&self, path: String) -> Option<Vec<u8>>;
+    fn watcher(&self) -> &FileWatcher;
+}
+
+#[jni_interface]
+trait WatchRegistry {
+    fn register(&self, listener: &impl WatchListener) -> bool;
+}
+
 #[jni_struct_impl]
 impl FileWatcher {
     
@@ -23,12 +69,220 @@ impl FileWatcher {
             Some(value) => { println!("Received value {value}!") }
         }
         
-        return Some(1)
+        Some(1)
+    }
+
+    fn options(mode: WatchMode) -> WatchOptions {
+        let path = match mode {
+            WatchMode::Recursive => "/",
+            WatchMode::NonRecursive => ".",
+        };
+
+        WatchOptions {
+            path: path.to_string(),
+            mode,
+            excluded_paths: vec![],
+            labels: HashMap::new(),
+            max_depth: None,
+        }
+    }
+
+    fn describe(event: WatchEvent) -> WatchEvent {
+        match event {
+            WatchEvent::Created { path } => println!("Created {path}"),
+            WatchEvent::Renamed(from, to) => println!("Renamed {from} to {to}"),
+            WatchEvent::Deleted => println!("Deleted"),
+        }
+
+        WatchEvent::Deleted
+    }
+    fn parse_mode(name: String) -> Result<WatchMode, String> {
+        match name.as_str() {
+            "recursive" => Ok(WatchMode::Recursive),
+            "non_recursive" => Ok(WatchMode::NonRecursive),
+            _ => Err(format!("Unknown watch mode {name}")),
+        }
+    }
+
+    fn validate(path: String) -> Result<(), std::io::Error> {
+        std::fs::metadata(path).map(|_| ())
+    }
+    fn notify_changes(listener: &mut impl WatchListener, changes_count: u32) -> u8 {
+        listener.on_changes(changes_count)
+    }
+    fn watch_in_background(listener: impl WatchListener + Send + 'static, changes: Vec<u32>) {
+        std::thread::spawn(move || {
+            for changes_count in changes {
+                listener.on_changes(changes_count);
+            }
+        });
+    }
+    fn report_error(listener: &mut impl WatchListener, message: Option<String>, code: Option<u32>) -> Option<bool> {
+        listener.on_error(message, code)
+    }
+    fn configure(listener: &mut impl WatchListener, path: String) -> Vec<String> {
+        let options = listener.options_for(path, &WatchMode::Recursive);
+        listener.ignored(&options)
+    }
+    fn read_contents(listener: &mut impl WatchListener, path: String) -> Option<Vec<u8>> {
+        listener.contents(path)
+    }
+    fn is_watched_by(&self, listener: &mut impl WatchListener) -> bool {
+        std::ptr::eq(self, listener.watcher())
+    }
+    fn register(registry: &mut impl WatchRegistry, listener: &mut impl WatchListener) -> bool {
+        registry.register(&*listener)
+    }
+    fn find(
+        path: Option<String>,
+        mode: Option<WatchMode>,
+        max_depth: Option<i64>,
+        excluded_paths: Option<Vec<String>>,
+        content: Option<Vec<u8>>,
+    ) -> Option<WatchOptions> {
+        let path = path?;
+
+        Some(WatchOptions {
+            path,
+            mode: mode.unwrap_or(WatchMode::NonRecursive),
+            excluded_paths: excluded_paths.unwrap_or_default(),
+            labels: HashMap::new(),
+            max_depth: max_depth.or(content.map(|content| content.len() as i64)),
+        })
+    }
+    fn truncate(text: String, max_len: usize, offset: isize, small: i8, medium: i16) -> char {
+        let start = (offset.max(0) as usize).min(text.len());
+        let skip = (small as i64 + medium as i64).max(0) as usize;
+
+        text.chars()
+            .skip(start + skip)
+            .take(max_len)
+            .next()
+            .unwrap_or(' ')
+    }
+    fn watchers(count: i32) -> Vec<FileWatcher> {
+        (0..count).map(|_| FileWatcher {}).collect()
+    }
+
+    fn all_options(modes: Vec<WatchMode>, excluded_paths: Vec<Vec<String>>) -> Vec<WatchOptions> {
+        modes.into_iter()
+            .zip(excluded_paths)
+            .map(|(mode, excluded_paths)| WatchOptions {
+                path: ".".to_string(),
+                mode,
+                excluded_paths,
+                labels: HashMap::new(),
+            max_depth: None,
+            })
+            .collect()
     }
 
+    fn sizes(sizes: Vec<Option<i64>>) -> Vec<i64> {
+        sizes.into_iter().flatten().collect()
+    }
+
+    fn recursive_paths(modes: HashMap<String, WatchMode>) -> BTreeMap<String, u32> {
+        modes.into_iter()
+            .filter(|(_, mode)| matches!(mode, WatchMode::Recursive))
+            .map(|(path, _)| {
+                let depth = path.split('/').filter(|part| !part.is_empty()).count() as u32;
+                (path, depth)
+            })
+            .collect()
+    }
+
+    fn excluded(paths: Vec<String>, extra_paths: BTreeSet<String>) -> HashSet<String> {
+        paths.into_iter().chain(extra_paths).collect()
+    }
+
+    async fn count_entries(path: String) -> Result<u32, std::io::Error> {
+        let entries = std::fs::read_dir(path)?;
+        Ok(entries.count() as u32)
+    }
+
+    fn split_path(path: String) -> (String, String) {
+        match path.rsplit_once('/') {
+            Some((parent, name)) => (parent.to_string(), name.to_string()),
+            None => (String::new(), path),
+        }
+    }
+
+    fn largest(entries: Vec<(String, i64, bool, WatchMode)>) -> (String, i64, bool) {
+        entries.into_iter()
+            .filter(|(_, _, _, mode)| matches!(mode, WatchMode::Recursive))
+            .max_by_key(|(_, size, _, _)| *size)
+            .map(|(path, size, is_dir, _)| (path, size, is_dir))
+            .unwrap_or_default()
+    }
+
+    fn extension(path: &str) -> &str {
+        path.rsplit_once('.')
+            .map(|(_, extension)| extension)
+            .unwrap_or_default()
+    }
+
+    fn checksum(content: &DirectBuffer) -> u32 {
+        content.iter().fold(0u32, |checksum, byte| checksum.wrapping_mul(31).wrapping_add(*byte as u32))
+    }
+
+    fn empty_snapshot(size: i32) -> Box<DirectBuffer> {
+        vec![0; size.max(0) as usize].into_boxed_slice()
+    }
+
+    fn histogram(sizes: &[i64], buckets: i32) -> Vec<i32> {
+        let buckets = buckets.max(1) as usize;
+        let largest = sizes.iter().copied().max().unwrap_or_default().max(1);
+        let mut histogram = vec![0; buckets];
+
+        for size in sizes {
+            let bucket = (size.max(&0) * (buckets as i64 - 1) / largest) as usize;
+            histogram[bucket] += 1;
+        }
+
+        histogram
+    }
 
     // fn new() -> FileWatcher {
     //     FileWatcher {
     //     }
     // }
 }
+
+#[jni_struct_impl]
+impl WatchStats {
+    fn create() -> WatchStats {
+        WatchStats {
+            events: 0,
+            last_path: None,
+        }
+    }
+
+    fn record(&mut self, path: String) {
+        self.events += 1;
+        self.last_path = Some(path);
+    }
+
+    fn events(&self) -> u64 {
+        self.events
+    }
+
+    fn last_path(&self) -> Option<String> {
+        self.last_path.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn signatures_use_the_package_path() {
+        assert_eq!(
+            crate::jni_signature("(L<PKG_NAME>/WatchMode;[L<PKG_NAME>/WatchOptions;)L<PKG_NAME>/FileWatcher;"),
+            "(Lcom/jetpackduba/kotars/example/WatchMode;[Lcom/jetpackduba/kotars/example/WatchOptions;)Lcom/jetpackduba/kotars/example/FileWatcher;",
+        );
+    }
+
+    #[test]
+    fn exports_use_the_mangled_package_path() {
+        assert_eq!(jni_mangled_package_path!(), "com_jetpackduba_kotars_example_");
+    }
+}
@@ -1,7 +1,31 @@
-use std::fmt::format;
+use std::collections::BTreeSet;
 
 use serde::{Deserialize, Serialize};
 
+/// Directory where the macros write the metadata of the bindings, set by cargo-kotars when it checks the crate
+pub const KOTARS_METADATA_DIR_ENV: &str = "KOTARS_METADATA_DIR";
+
+pub const BINDING_START: &str = "JNI_BINDING_START";
+pub const BINDING_END: &str = "JNI_BINDING_END";
+/// Version of the metadata format, to be increased whenever cargo-kotars can no longer read the previous one
+pub const BINDING_VERSION: u32 = 1;
+
+/// Lines of the metadata block of a binding. The start line is tagged with the metadata version and the Rust
+/// item the binding comes from, so cargo-kotars can point to it when the block is malformed.
+pub fn binding_block_lines(item: &str, record: &str) -> [String; 3] {
+    [
+        format!("{BINDING_START} v{BINDING_VERSION} {item}"),
+        record.to_string(),
+        BINDING_END.to_string(),
+    ]
+}
+
+/// Linker sections of native libraries holding the metadata blocks, separated by NUL bytes. Mach-O and PE
+/// impose their own naming rules, so each format uses a different name.
+pub const METADATA_SECTION_ELF: &str = "kotars_metadata";
+pub const METADATA_SECTION_MACHO: &str = "__kotars";
+pub const METADATA_SECTION_PE: &str = ".kotars";
+
 #[derive(Clone, Serialize, Deserialize)]
 pub enum Parameter {
     Typed {
@@ -19,6 +43,7 @@ pub struct Function {
     pub name: String,
     pub parameters: Vec<Parameter>,
     pub return_type: Option<JniType>,
+    pub is_async: bool,
 }
 
 impl Function {
@@ -27,6 +52,53 @@ impl Function {
             .iter()
             .any(|p| matches!(p, Parameter::Receiver { .. }))
     }
+
+    pub fn has_unsigned_types(&self) -> bool {
+        let has_unsigned_parameters = self.parameters
+            .iter()
+            .any(|p| matches!(p, Parameter::Typed { ty, .. } if ty.is_kotlin_inline_class()));
+        let has_unsigned_return = self.return_type
+            .as_ref()
+            .is_some_and(|ty| ty.is_kotlin_inline_class());
+
+        has_unsigned_parameters || has_unsigned_return
+    }
+
+    /// Kotlin mangles the JVM name of functions using unsigned types, so interfaces get an extra
+    /// method with signed types that can be called through JNI.
+    pub fn interface_jni_method_name(&self) -> String {
+        let name = string_to_camel_case(&self.name);
+
+        if self.has_unsigned_types() {
+            format!("{name}Jni")
+        } else {
+            name
+        }
+    }
+
+    pub fn resolve_enums(&mut self, enum_names: &[String]) {
+        for param in self.parameters.iter_mut() {
+            if let Parameter::Typed { ty, .. } = param {
+                ty.resolve_enums(enum_names);
+            }
+        }
+
+        if let Some(return_type) = self.return_type.as_mut() {
+            return_type.resolve_enums(enum_names);
+        }
+    }
+
+    pub fn collect_tuple_arities(&self, arities: &mut BTreeSet<usize>) {
+        for param in self.parameters.iter() {
+            if let Parameter::Typed { ty, .. } = param {
+                ty.collect_tuple_arities(arities);
+            }
+        }
+
+        if let Some(return_type) = self.return_type.as_ref() {
+            return_type.collect_tuple_arities(arities);
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -35,6 +107,51 @@ pub struct RsStruct {
     pub fields: Vec<Field>,
 }
 
+/// Synchronization of the instances of a #[jni_class] struct shared by the Kotlin threads, set by its `sync` argument
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum ClassSync {
+    /// Left to the Kotlin side, functions must not be called from several threads at once
+    #[default]
+    None,
+    /// Functions run one at a time
+    Mutex,
+    /// Functions taking `&self` run at the same time, the ones taking `&mut self` run alone
+    RwLock,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RsClass {
+    pub name: String,
+    #[serde(default)]
+    pub sync: ClassSync,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RsEnum {
+    pub name: String,
+    pub variants: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RsSealedClass {
+    pub name: String,
+    pub variants: Vec<RsStruct>,
+}
+
+impl RsSealedClass {
+    pub fn resolve_enums(&mut self, enum_names: &[String]) {
+        for variant in self.variants.iter_mut() {
+            variant.resolve_enums(enum_names);
+        }
+    }
+
+    pub fn collect_tuple_arities(&self, arities: &mut BTreeSet<usize>) {
+        for variant in self.variants.iter() {
+            variant.collect_tuple_arities(arities);
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct RsInterface {
     pub name: String,
@@ -56,6 +173,18 @@ impl RsStruct {
     pub fn all_fields_are_public(&self) -> bool {
         self.fields.iter().any(|p| !p.is_public) // any is used because an empty list of fields is permitted
     }
+
+    pub fn resolve_enums(&mut self, enum_names: &[String]) {
+        for field in self.fields.iter_mut() {
+            field.ty.resolve_enums(enum_names);
+        }
+    }
+
+    pub fn collect_tuple_arities(&self, arities: &mut BTreeSet<usize>) {
+        for field in self.fields.iter() {
+            field.ty.collect_tuple_arities(arities);
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -77,65 +206,128 @@ impl Field {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum JniType {
+    Int8,
+    Int16,
     Int32,
     Int64,
+    UInt8,
+    UInt16,
+    UInt32,
     UInt64,
+    ISize,
+    USize,
     Float32,
     Float64,
     String,
     Boolean,
+    Char,
     Receiver(String),
     CustomType(String),
+    Enum(String),
     Interface(String),
     Option(Box<JniType>),
     ByteArray,
+    /// `Vec` or slice of a primitive type, copied from and into the Kotlin array of that primitive, like `IntArray`
+    PrimitiveArray(Box<JniType>),
+    /// Memory of a direct `ByteBuffer` borrowed by a parameter, without copying it
+    DirectBuffer,
+    /// Memory owned by Rust returned as a direct `ByteBuffer`, wrapped by a Kotlin `RustBuffer` that frees it
+    RustBuffer,
     Vec(Box<JniType>),
+    HashSet(Box<JniType>),
+    BTreeSet(Box<JniType>),
+    HashMap(Box<JniType>, Box<JniType>),
+    BTreeMap(Box<JniType>, Box<JniType>),
+    Tuple(Vec<JniType>),
+    Result(Box<JniType>),
     Void,
 }
 
-impl From<String> for JniType {
-    fn from(value: String) -> Self {
-        match value.as_str() {
-            "i32" => JniType::Int32,
-            "i64" => JniType::Int64,
-            "u64" => JniType::UInt64,
-            "f32" => JniType::Float32,
-            "f64" => JniType::Float64,
-            "String" => JniType::String,
-            "bool" => JniType::Boolean,
-            _ => {
-                let interface_prefix = "impl ";
-                let option_prefix = "Option";
-                let value_without_spaces = value.replace(' ', "");
-
-                if value_without_spaces == "Vec<u8>" {
-                    JniType::ByteArray
-                } else if value_without_spaces.starts_with("Vec<") && value_without_spaces.ends_with(">") {
-                    let inner_ty = value_without_spaces.strip_prefix("Vec<").unwrap().strip_suffix(">").unwrap().to_string();
-                    let inner_ty = Box::new(JniType::from(inner_ty));
-                    JniType::Vec(inner_ty)
-                } else if value.starts_with(option_prefix) {
-
-                    let ty = value.strip_prefix("Option < ").expect("Removing option prefix failed").strip_suffix(" >").expect("Removing option suffix failed");
-
-                    JniType::Option(Box::new(JniType::from(ty.to_string())))
-                } else if value.starts_with(interface_prefix) {
-                    let range_start = interface_prefix.len();
-                    let range_end = value.len();
-                    let interface_name = &value[range_start..range_end];
-
-                    JniType::Interface(interface_name.to_string())
-                } else {
-                    JniType::CustomType(value.to_string())
+impl JniType {
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, JniType::UInt8 | JniType::UInt16 | JniType::UInt32 | JniType::UInt64)
+    }
+
+    /// Primitives with a specialized Kotlin array. Unsigned arrays are still experimental in Kotlin and every
+    /// `char` has to be checked on its own, so both are kept in regular arrays.
+    pub fn has_primitive_array(&self) -> bool {
+        matches!(
+            self,
+            JniType::Int8 | JniType::Int16 | JniType::Int32 | JniType::Int64 | JniType::Float32 | JniType::Float64 | JniType::Boolean
+        )
+    }
+
+    /// Unsigned types are inline classes in Kotlin, nullable or not, and Kotlin mangles the JVM
+    /// names of the functions using them.
+    pub fn is_kotlin_inline_class(&self) -> bool {
+        match self {
+            JniType::Option(ty) => ty.is_unsigned(),
+            JniType::Result(ty) => ty.is_kotlin_inline_class(),
+            ty => ty.is_unsigned(),
+        }
+    }
+
+    /// Custom types can't be told apart from enums when the macros parse a single item, so
+    /// `cargo-kotars` uses this once every `JNI_ENUM` header has been collected.
+    pub fn resolve_enums(&mut self, enum_names: &[String]) {
+        match self {
+            JniType::CustomType(name) if enum_names.contains(name) => {
+                *self = JniType::Enum(name.clone());
+            }
+            JniType::Option(ty) | JniType::Vec(ty) | JniType::HashSet(ty) | JniType::BTreeSet(ty) | JniType::Result(ty) => {
+                ty.resolve_enums(enum_names)
+            }
+            JniType::HashMap(key_ty, value_ty) | JniType::BTreeMap(key_ty, value_ty) => {
+                key_ty.resolve_enums(enum_names);
+                value_ty.resolve_enums(enum_names);
+            }
+            JniType::Tuple(element_types) => {
+                for ty in element_types.iter_mut() {
+                    ty.resolve_enums(enum_names);
                 }
             }
+            _ => {}
         }
     }
+
+    /// Adds the arity of every tuple found in this type, including nested ones.
+    pub fn collect_tuple_arities(&self, arities: &mut BTreeSet<usize>) {
+        match self {
+            JniType::Option(ty) | JniType::Vec(ty) | JniType::HashSet(ty) | JniType::BTreeSet(ty) | JniType::Result(ty) => {
+                ty.collect_tuple_arities(arities)
+            }
+            JniType::HashMap(key_ty, value_ty) | JniType::BTreeMap(key_ty, value_ty) => {
+                key_ty.collect_tuple_arities(arities);
+                value_ty.collect_tuple_arities(arities);
+            }
+            JniType::Tuple(element_types) => {
+                arities.insert(element_types.len());
+
+                for ty in element_types.iter() {
+                    ty.collect_tuple_arities(arities);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Tuples of 2 and 3 elements use Kotlin's `Pair` and `Triple`, any other arity uses a `TupleN`
+/// data class generated by `cargo-kotars`.
+pub fn tuple_class_name(arity: usize) -> String {
+    match arity {
+        2 => "Pair".to_string(),
+        3 => "Triple".to_string(),
+        _ => format!("Tuple{arity}"),
+    }
+}
+
+pub fn is_generated_tuple(arity: usize) -> bool {
+    !matches!(arity, 2 | 3)
 }
 
 pub fn string_to_camel_case(text: &str) -> String {
-    text.to_string()
-        .split(['_', ' '])
+    text.split(['_', ' '])
         .enumerate()
         .map(|(index, word)| {
             let word = word.to_string();
@@ -156,3 +348,78 @@ pub fn string_to_camel_case(text: &str) -> String {
         .collect::<Vec<String>>()
         .join("")
 }
+
+/// Escapes a name following the JNI rules for the names of native method implementations, so it can
+/// be part of an exported symbol. Packages can be separated by either `.` or `/`.
+pub fn jni_mangle(name: &str) -> String {
+    let mut mangled = String::with_capacity(name.len());
+
+    for c in name.chars() {
+        match c {
+            '.' | '/' => mangled.push('_'),
+            '_' => mangled.push_str("_1"),
+            ';' => mangled.push_str("_2"),
+            '[' => mangled.push_str("_3"),
+            c if c.is_ascii_alphanumeric() => mangled.push(c),
+            c => {
+                let mut utf16 = [0u16; 2];
+
+                for unit in c.encode_utf16(&mut utf16) {
+                    mangled.push_str(&format!("_0{unit:04x}"));
+                }
+            }
+        }
+    }
+
+    mangled
+}
+
+/// Name of the exported symbol implementing a native method. Overloaded methods require the long name,
+/// which includes the arguments signature (the part of the method descriptor between parentheses).
+pub fn jni_native_method_name(class_path: &str, method_name: &str, arguments_signature: Option<&str>) -> String {
+    let short_name = format!("Java_{}_{}", jni_mangle(class_path), jni_mangle(method_name));
+
+    match arguments_signature {
+        Some(arguments_signature) => format!("{short_name}__{}", jni_mangle(arguments_signature)),
+        None => short_name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mangles_escaped_characters() {
+        assert_eq!(jni_mangle("com.example/my_class"), "com_example_my_1class");
+        assert_eq!(jni_mangle("[Ljava/lang/String;"), "_3Ljava_lang_String_2");
+        assert_eq!(jni_mangle("Outer$Inner"), "Outer_00024Inner");
+        assert_eq!(jni_mangle("café"), "caf_000e9");
+    }
+
+    #[test]
+    fn mangles_supplementary_characters_as_surrogate_pairs() {
+        assert_eq!(jni_mangle("a\u{1F600}"), "a_0d83d_0de00");
+    }
+
+    #[test]
+    fn builds_overloaded_long_names() {
+        assert_eq!(
+            jni_native_method_name("com/example/FileWatcherObj", "count_items", Some("[Ljava/lang/String;I")),
+            "Java_com_example_FileWatcherObj_count_1items___3Ljava_lang_String_2I",
+        );
+        assert_eq!(jni_native_method_name("FileWatcherObj", "countItems", None), "Java_FileWatcherObj_countItems");
+    }
+
+    /// The macros mangle names with a package placeholder, which `jni_init!` replaces by the mangled package
+    #[test]
+    fn replaces_the_mangled_package_placeholder() {
+        let placeholder = "<PKG_NAME>/";
+        let symbol = jni_native_method_name(&format!("{placeholder}FileWatcherObj"), "countItems", Some(&format!("L{placeholder}WatchMode;")));
+
+        assert_eq!(
+            symbol.replace(&jni_mangle(placeholder), &jni_mangle("com/my_app/")),
+            jni_native_method_name("com/my_app/FileWatcherObj", "countItems", Some("Lcom/my_app/WatchMode;")),
+        );
+    }
+}
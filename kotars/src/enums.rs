@@ -0,0 +1,259 @@
+use kotars_common::{Field, RsEnum, RsSealedClass, RsStruct};
+use quote::quote;
+use syn::__private::TokenStream2;
+use syn::ItemEnum;
+
+use crate::structs::{
+    fields_from_syn, generate_struct_fields_bindings, generate_struct_fields_from_jobject, generate_struct_fields_mapping_into_array,
+    generate_struct_fields_members, jni_type_to_jni_method_signature_type,
+};
+use crate::{full_header_comment, rust_property_to_jni_type};
+
+pub fn rs_enum_from_syn(item_enum: &ItemEnum) -> RsEnum {
+    let variants = item_enum
+        .variants
+        .iter()
+        .map(|variant| variant.ident.to_string())
+        .collect::<Vec<String>>();
+
+    RsEnum {
+        name: item_enum.ident.to_string(),
+        variants,
+    }
+}
+
+pub fn generate_enum_conversions(rs_enum: &RsEnum) -> TokenStream2 {
+    let enum_name = &rs_enum.name;
+    let enum_token: TokenStream2 = syn::parse_str(enum_name).unwrap();
+
+    let variant_tokens = rs_enum
+        .variants
+        .iter()
+        .map(|variant| syn::parse_str::<TokenStream2>(variant).unwrap())
+        .collect::<Vec<TokenStream2>>();
+    let variant_names = &rs_enum.variants;
+    let expected_variants = rs_enum.variants.join(", ");
+
+    let enum_json = serde_json::to_string(rs_enum).unwrap();
+    let header_param = format!("JNI_ENUM {enum_json}");
+    let header_comments = full_header_comment(&rs_enum.name, header_param.as_str());
+
+    quote! {
+        impl crate::JniValue for #enum_token {}
+
+        #header_comments
+        impl <'local> crate::IntoEnv<'local, jni::objects::JObject<'local>> for #enum_token {
+            fn into_env(self, env: &mut std::cell::RefMut<'_, jni::JNIEnv<'local>>) -> jni::objects::JObject<'local> {
+                let package_name_for_signature = crate::JNI_PACKAGE_NAME.replace(".", "/");
+
+                let class_path = if package_name_for_signature.is_empty() {
+                    format!("{}", #enum_name)
+                } else {
+                    format!("{}/{}", package_name_for_signature, #enum_name)
+                };
+                let field_signature = format!("L{class_path};");
+
+                let variant_name = match self {
+                    #(#enum_token::#variant_tokens => #variant_names,)*
+                };
+
+                let error_msg = format!("Find class failed for {class_path}");
+                let class = env.find_class(&class_path).expect(error_msg.as_str());
+
+                let error_msg = format!("Could not get variant {variant_name} of {class_path}");
+                env.get_static_field(class, variant_name, field_signature)
+                    .and_then(|value| value.l())
+                    .expect(error_msg.as_str())
+            }
+        }
+
+        impl <'local> crate::IntoEnv<'local, #enum_token> for jni::objects::JObject<'local> {
+            fn into_env(self, env: &mut std::cell::RefMut<'_, jni::JNIEnv<'local>>) -> #enum_token {
+                let name: jni::objects::JString = env.call_method(&self, "name", "()Ljava/lang/String;", &[])
+                    .and_then(|value| value.l())
+                    .expect("Could not get the name of the enum variant")
+                    .into();
+
+                let name: String = env
+                    .get_string(&name)
+                    .expect("Couldn't get java string!")
+                    .into();
+
+                match name.as_str() {
+                    #(#variant_names => #enum_token::#variant_tokens,)*
+                    _ => panic!("Unknown variant {} for enum {}, expected one of: {}", name, #enum_name, #expected_variants),
+                }
+            }
+        }
+    }
+}
+
+pub fn rs_sealed_class_from_syn(item_enum: &ItemEnum) -> RsSealedClass {
+    let variants = item_enum
+        .variants
+        .iter()
+        .map(|variant| {
+            let fields = fields_from_syn(&variant.fields)
+                .into_iter()
+                .map(|field| Field {
+                    is_public: true,
+                    ..field
+                })
+                .collect::<Vec<Field>>();
+
+            RsStruct {
+                name: variant.ident.to_string(),
+                fields,
+            }
+        })
+        .collect::<Vec<RsStruct>>();
+
+    RsSealedClass {
+        name: item_enum.ident.to_string(),
+        variants,
+    }
+}
+
+pub fn generate_sealed_class_conversions(rs_sealed_class: &RsSealedClass) -> TokenStream2 {
+    let sealed_class_name = &rs_sealed_class.name;
+    let sealed_class_token: TokenStream2 = syn::parse_str(sealed_class_name).unwrap();
+
+    let variants_into_jobject = rs_sealed_class
+        .variants
+        .iter()
+        .map(|variant| variant_into_jobject(&sealed_class_token, variant))
+        .collect::<Vec<TokenStream2>>();
+
+    let variants_from_jobject = rs_sealed_class
+        .variants
+        .iter()
+        .map(|variant| variant_from_jobject(&sealed_class_token, variant))
+        .collect::<Vec<TokenStream2>>();
+
+    let sealed_class_json = serde_json::to_string(rs_sealed_class).unwrap();
+    let header_param = format!("JNI_SEALED_CLASS {sealed_class_json}");
+    let header_comments = full_header_comment(&rs_sealed_class.name, header_param.as_str());
+
+    quote! {
+        impl crate::JniValue for #sealed_class_token {}
+
+        #header_comments
+        impl <'local> crate::IntoEnv<'local, jni::objects::JObject<'local>> for #sealed_class_token {
+            fn into_env(self, env: &mut std::cell::RefMut<'_, jni::JNIEnv<'local>>) -> jni::objects::JObject<'local> {
+                let package_name_for_signature = crate::JNI_PACKAGE_NAME.replace(".", "/");
+                let rc_env = std::rc::Rc::new(std::cell::RefCell::new(env));
+
+                let sealed_class_path = if package_name_for_signature.is_empty() {
+                    format!("{}", #sealed_class_name)
+                } else {
+                    format!("{}/{}", package_name_for_signature, #sealed_class_name)
+                };
+
+                match self {
+                    #(#variants_into_jobject)*
+                }
+            }
+        }
+
+        impl <'local> crate::IntoEnv<'local, #sealed_class_token> for jni::objects::JObject<'local> {
+            fn into_env(self, env: &mut std::cell::RefMut<'_, jni::JNIEnv<'local>>) -> #sealed_class_token {
+                let package_name_for_signature = crate::JNI_PACKAGE_NAME.replace(".", "/");
+                let rc_env = std::rc::Rc::new(std::cell::RefCell::new(env));
+
+                let sealed_class_path = if package_name_for_signature.is_empty() {
+                    format!("{}", #sealed_class_name)
+                } else {
+                    format!("{}/{}", package_name_for_signature, #sealed_class_name)
+                };
+
+                #(#variants_from_jobject)*
+
+                panic!("Object is not an instance of any of the subclasses of {sealed_class_path}")
+            }
+        }
+    }
+}
+
+fn variant_into_jobject(sealed_class_token: &TokenStream2, variant: &RsStruct) -> TokenStream2 {
+    let variant_name = &variant.name;
+    let variant_token: TokenStream2 = syn::parse_str(variant_name).unwrap();
+    let members = generate_struct_fields_members(&variant.fields);
+    let bindings = generate_struct_fields_bindings(&variant.fields);
+
+    let object_creation = if variant.fields.is_empty() {
+        quote! {
+            let mut env = rc_env.borrow_mut();
+            let error_msg = format!("Could not find class {class_path}");
+            let class = env.find_class(&class_path).expect(&error_msg);
+
+            let error_msg = format!("Could not get the instance of {class_path}");
+            env.get_static_field(class, "INSTANCE", format!("L{class_path};"))
+                .and_then(|value| value.l())
+                .expect(&error_msg)
+        }
+    } else {
+        let constructor_types_signature = variant
+            .fields
+            .iter()
+            .map(|field| jni_type_to_jni_method_signature_type(&field.ty))
+            .collect::<Vec<String>>()
+            .join("");
+        let constructor_signature = format!("({constructor_types_signature})V");
+
+        let transformations = variant
+            .fields
+            .iter()
+            .zip(bindings.iter())
+            .map(|(field, binding)| rust_property_to_jni_type(&field.ty, binding, binding))
+            .collect::<Vec<TokenStream2>>();
+        let params_into_array = generate_struct_fields_mapping_into_array(&variant.fields);
+
+        quote! {
+            let class = {
+                let mut env = rc_env.borrow_mut();
+                let error_msg = format!("Could not find class {class_path}");
+                env.find_class(&class_path).expect(&error_msg)
+            };
+
+            #(#transformations)*
+
+            let constructor_args: &[jni::objects::JValue] = &[#(#params_into_array,)*];
+
+            let mut env = rc_env.borrow_mut();
+            let error_msg = format!("New object failed {class_path}");
+            env.new_object(class, crate::jni_signature(#constructor_signature), constructor_args).expect(&error_msg)
+        }
+    };
+
+    quote! {
+        #sealed_class_token::#variant_token { #(#members: #bindings,)* } => {
+            let class_path = format!("{}${}", sealed_class_path, #variant_name);
+
+            #object_creation
+        }
+    }
+}
+
+fn variant_from_jobject(sealed_class_token: &TokenStream2, variant: &RsStruct) -> TokenStream2 {
+    let variant_name = &variant.name;
+    let variant_token: TokenStream2 = syn::parse_str(variant_name).unwrap();
+    let members = generate_struct_fields_members(&variant.fields);
+    let bindings = generate_struct_fields_bindings(&variant.fields);
+
+    let fields_transformations = generate_struct_fields_from_jobject(variant_name, &variant.fields);
+
+    quote! {
+        let class_path = format!("{}${}", sealed_class_path, #variant_name);
+        let is_variant = {
+            let mut env = rc_env.borrow_mut();
+            let error_msg = format!("Could not check if object is an instance of {class_path}");
+            env.is_instance_of(&self, class_path.as_str()).expect(&error_msg)
+        };
+
+        if is_variant {
+            #(#fields_transformations)*
+
+            return #sealed_class_token::#variant_token { #(#members: #bindings,)* };
+        }
+    }
+}
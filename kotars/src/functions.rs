@@ -1,26 +1,38 @@
+use proc_macro2::Span;
 use quote::quote;
 use syn::__private::TokenStream2;
 use kotars_common::{Function, JniType, Parameter, string_to_camel_case};
-use crate::types_transformations::{transform_jni_type_to_rust, transform_rust_to_jni_type};
-use crate::full_header_comment;
+use crate::structs::jni_type_to_primitive_array;
+use crate::types_transformations::{BorrowedClass, primitive_array_values, transform_jlong_to_receiver, transform_jbytebuffer_to_slice, transform_jni_type_to_rust, transform_jobject_to_class, transform_jobject_to_global_interface, transform_rust_to_jni_type, transform_rust_to_jobject};
+use crate::{full_header_comment, jni_export_name, kotlin_jni_method_signature_type};
 
+/// Generates the bindings of the functions, given with the spans of their parameters to point the errors of the
+/// generated code at the parameter they come from
 pub fn generate_rust_jni_binding_functions(
     struct_name: &str,
-    functions: &[Function],
+    functions: &[(Function, Vec<Span>)],
 ) -> Vec<TokenStream2> {
-    functions.iter().map(|func| {
-        generate_rust_jni_binding_function(struct_name, func)
+    functions.iter().map(|(func, parameter_spans)| {
+        // Functions sharing their Kotlin name are overloads, which the JVM resolves by their long name
+        let is_overloaded = functions
+            .iter()
+            .filter(|(other, _)| string_to_camel_case(&other.name) == string_to_camel_case(&func.name))
+            .count() > 1;
+
+        generate_rust_jni_binding_function(struct_name, func, parameter_spans, is_overloaded)
     }).collect()
 }
 
-fn generate_rust_jni_binding_function(struct_name: &str, func: &Function) -> TokenStream2 {
+fn generate_rust_jni_binding_function(struct_name: &str, func: &Function, parameter_spans: &[Span], is_overloaded: bool) -> TokenStream2 {
     let fn_name = &func.name;
     let fn_name_for_jni = string_to_camel_case(fn_name);
 
     let obj_suffix = "Obj";
+    let class_name = format!("{struct_name}{obj_suffix}");
 
-    // Name of the JNI method containing
-    let method_name = format!("Java_{struct_name}{obj_suffix}_{fn_name_for_jni}");
+    let arguments_signature = is_overloaded.then(|| jni_arguments_signature(func));
+    let export_name = jni_export_name(&class_name, &fn_name_for_jni, arguments_signature.as_deref());
+    let method_name = format!("jni_{class_name}_{fn_name}");
 
     let mut jni_function_parameters: Vec<TokenStream2> = vec![
         quote! { mut env: jni::JNIEnv<'local> },
@@ -28,24 +40,55 @@ fn generate_rust_jni_binding_function(struct_name: &str, func: &Function) -> Tok
     ];
 
     let mut jni_to_rust_types_transformations: Vec<TokenStream2> = Vec::new();
+    let mut copy_back_transformations: Vec<TokenStream2> = Vec::new();
+    let mut borrowed_classes: Vec<BorrowedClass> = Vec::new();
 
-    for param in &func.parameters {
+    for (param, span) in func.parameters.iter().zip(parameter_spans) {
         match param {
             Parameter::Typed {
                 name,
                 ty,
                 is_borrow,
-                is_mutable
+                is_mutable,
             } => {
                 let name = name.to_string();
                 let rust_jni_ty = jni_type_to_jni_type(ty, false);
-                let transformation = transform_jni_type_to_rust(ty, &name, false);
+                let transformation = match ty {
+                    // Classes are locked for reading, or for writing when they are borrowed mutably
+                    JniType::CustomType(class_name) if *is_borrow => {
+                        let transformation = transform_jobject_to_class(&name, class_name, *is_mutable, &borrowed_classes);
+                        borrowed_classes.push(BorrowedClass {
+                            param_name: name.clone(),
+                            ty: class_name.clone(),
+                            is_mutable: *is_mutable,
+                        });
+
+                        transformation
+                    }
+                    JniType::DirectBuffer => transform_jbytebuffer_to_slice(&name, *is_mutable),
+                    JniType::Interface(interface_name) if !*is_borrow => transform_jobject_to_global_interface(&name, interface_name, *span),
+                    ty => transform_jni_type_to_rust(ty, &name, *is_borrow),
+                };
 
                 let name = syn::parse_str::<TokenStream2>(&name).unwrap();
                 jni_function_parameters.push(quote! { #name: #rust_jni_ty });
+
+                let is_mutable_copy = *is_borrow && *is_mutable && matches!(ty, JniType::String | JniType::ByteArray | JniType::PrimitiveArray(_));
+
+                // Arrays borrowed mutably are written back into the Kotlin array once the function returns
+                if is_mutable_copy && !matches!(ty, JniType::String) {
+                    let (save_array, copy_back) = copy_back_array(&name, ty);
+                    jni_to_rust_types_transformations.push(save_array);
+                    copy_back_transformations.push(copy_back);
+                }
+
                 jni_to_rust_types_transformations.push(transformation);
+
+                if is_mutable_copy {
+                    jni_to_rust_types_transformations.push(quote! { let mut #name = #name; });
+                }
             }
-            Parameter::Receiver { .. } => {
+            Parameter::Receiver { is_mutable } => {
                 let name = "jobject".to_string();
                 let ty = JniType::Receiver(struct_name.to_string());
 
@@ -53,12 +96,21 @@ fn generate_rust_jni_binding_function(struct_name: &str, func: &Function) -> Tok
                 let name_token = syn::parse_str::<TokenStream2>(&name).unwrap();
                 jni_function_parameters.push(quote! { #name_token: #jni_ty });
 
-                let transformation = transform_jni_type_to_rust(&ty, &name, false);
+                let transformation = transform_jlong_to_receiver(&name, struct_name, *is_mutable);
                 jni_to_rust_types_transformations.push(transformation);
+                borrowed_classes.push(BorrowedClass {
+                    param_name: name,
+                    ty: struct_name.to_string(),
+                    is_mutable: *is_mutable,
+                });
             }
         }
     }
 
+    if func.is_async {
+        jni_function_parameters.push(quote! { completable_future: jni::objects::JObject<'local> });
+    }
+
     let fn_owner = syn::parse_str::<TokenStream2>(struct_name).unwrap();
     let fn_to_call = syn::parse_str::<TokenStream2>(fn_name).unwrap();
 
@@ -70,9 +122,9 @@ fn generate_rust_jni_binding_function(struct_name: &str, func: &Function) -> Tok
                     name,
                     ty,
                     is_borrow,
-                    is_mutable
+                    is_mutable,
                 } => {
-                    let name = rust_fn_call_from_jni_type(ty, name);
+                    let name = rust_fn_call_from_jni_type(ty, name, *is_borrow, *is_mutable);
                     syn::parse_str::<TokenStream2>(&name).unwrap()
                 }
                 Parameter::Receiver { is_mutable } => {
@@ -91,57 +143,201 @@ fn generate_rust_jni_binding_function(struct_name: &str, func: &Function) -> Tok
 
     let rust_fn_call = quote! { let result = <#fn_owner>::#fn_to_call(#(#rust_fn_call_params,)*); };
 
-    let return_signature = match &func.return_type {
-        None => { quote! {} }
-        Some(ty) => {
-            let ret_type = jni_type_to_jni_type(ty, false);
-            quote! { -> #ret_type }
+    let (return_type, throw_on_error) = match &func.return_type {
+        Some(JniType::Result(ty)) => {
+            let throw_on_error = quote! {
+                let result = match result {
+                    Ok(result) => result,
+                    Err(e) => {
+                        let mut env = rc_env.borrow_mut();
+                        crate::throw_rust_exception(&mut env, e.to_string());
+                        return Default::default();
+                    }
+                };
+            };
+
+            let return_type = match ty.as_ref() {
+                JniType::Void => None,
+                ty => Some(ty.clone()),
+            };
+
+            (return_type, throw_on_error)
         }
+        return_type => (return_type.clone(), quote! {}),
     };
 
-    let result_variable = quote! { result };
+    let (return_signature, function_body) = if func.is_async {
+        let return_signature = quote! { -> jni::sys::jlong };
+        let function_body = generate_async_function_body(&func.name, struct_name, rust_fn_call, throw_on_error, &return_type);
 
-    let (transform_return, return_statement) = if let Some(ty) = &func.return_type {
-        let transform = transform_rust_to_jni_type(ty, &result_variable.to_string(), false, false);
-        let return_statement = quote! { return #result_variable; };
-        (transform, return_statement)
+        (return_signature, function_body)
     } else {
-        (quote!(), quote!())
+        let return_signature = match &return_type {
+            None => { quote! {} }
+            Some(ty) => {
+                let ret_type = jni_type_to_jni_type(ty, false);
+                quote! { -> #ret_type }
+            }
+        };
+
+        let result_variable = quote! { result };
+
+        let (transform_return, return_statement) = if let Some(ty) = &return_type {
+            let transform = transform_rust_to_jni_type(ty, &result_variable.to_string(), false);
+            let return_statement = quote! { return #result_variable; };
+            (transform, return_statement)
+        } else {
+            (quote!(), quote!())
+        };
+
+        let function_body = quote! {
+            #rust_fn_call
+            #throw_on_error
+            #transform_return
+            #(#copy_back_transformations)*
+            #return_statement
+        };
+
+        (return_signature, function_body)
     };
 
-    let method_name_token_stream = syn::parse_str::<TokenStream2>(method_name.as_str()).unwrap();
+    let method_name_token_stream = syn::parse_str::<TokenStream2>(&method_name).unwrap();
     let fn_serialized = serde_json::to_string(func).unwrap_or_else(|_| panic!("Serialization of function {fn_name} failed"));
 
+    let panic_fn_name = format!("{struct_name}::{fn_name}");
+
     let header_param = format!("JNI_FN_DATA {fn_serialized}");
-    let header_comments = full_header_comment(header_param.as_str());
+    let header_comments = full_header_comment(&panic_fn_name, header_param.as_str());
 
     quote! {
             #header_comments
-            #[no_mangle]
+            #[export_name = #export_name]
             pub extern "system" fn #method_name_token_stream<'local>(
                 #(#jni_function_parameters),*
             ) #return_signature {
-                let rc_env = std::rc::Rc::new(std::cell::RefCell::new(env));
+                let mut panic_env = unsafe { env.unsafe_clone() };
 
-                #(#jni_to_rust_types_transformations)*
+                crate::catch_jni_panic(&mut panic_env, #panic_fn_name, move || {
+                    let rc_env = std::rc::Rc::new(std::cell::RefCell::new(env));
 
-                #rust_fn_call
-                #transform_return
-                #return_statement
+                    #(#jni_to_rust_types_transformations)*
+
+                    #function_body
+                })
             }
         }
 }
 
-fn rust_fn_call_from_jni_type(jni_type: &JniType, name: &String) -> String {
+/// The future returned by the Rust function is run by the executor of `jni_init!`, which converts
+/// its output and completes the `CompletableFuture` awaited by the Kotlin suspend function.
+fn generate_async_function_body(
+    fn_name: &str,
+    struct_name: &str,
+    rust_fn_call: TokenStream2,
+    throw_on_error: TokenStream2,
+    return_type: &Option<JniType>,
+) -> TokenStream2 {
+    let task_fn_name = format!("{struct_name}::{fn_name}");
+
+    let transform_return = match return_type {
+        Some(ty) if !matches!(ty, JniType::Void) => transform_rust_to_jobject(ty, "result"),
+        _ => quote! {
+            let result = jni::objects::JObject::null();
+        },
+    };
+
+    quote! {
+        #rust_fn_call
+
+        let mut env = rc_env.borrow_mut();
+
+        crate::spawn_jni_task(&mut env, completable_future, #task_fn_name, result, |env, completable_future, result| {
+            let rc_env = std::rc::Rc::new(std::cell::RefCell::new(env));
+
+            #throw_on_error
+            #transform_return
+
+            let mut env = rc_env.borrow_mut();
+            env.call_method(completable_future, "complete", "(Ljava/lang/Object;)Z", &[(&result).into()])
+                .expect("Could not complete the CompletableFuture");
+        })
+    }
+}
+
+/// Arguments part of the descriptor of the Kotlin external function
+fn jni_arguments_signature(func: &Function) -> String {
+    let mut signature = func.parameters
+        .iter()
+        .map(|param| match param {
+            Parameter::Typed { ty, .. } => kotlin_jni_method_signature_type(ty),
+            Parameter::Receiver { .. } => "J".to_string(),
+        })
+        .collect::<String>();
+
+    if func.is_async {
+        signature.push_str("Ljava/util/concurrent/CompletableFuture;");
+    }
+
+    signature
+}
+
+/// Keeps the Kotlin array borrowed mutably, which is shadowed by its copy, and writes the copy back into it
+fn copy_back_array(name: &TokenStream2, ty: &JniType) -> (TokenStream2, TokenStream2) {
+    let array_name = syn::parse_str::<TokenStream2>(&format!("{name}_array")).unwrap();
+
+    let (array_type, set_region, values) = match ty {
+        JniType::PrimitiveArray(inner_ty) => {
+            let array = jni_type_to_primitive_array(inner_ty);
+            (array.array_type, array.set_region, primitive_array_values(&name.to_string(), inner_ty))
+        }
+        _ => {
+            let bytes = quote! {
+                unsafe { std::slice::from_raw_parts(#name.as_ptr() as *const jni::sys::jbyte, #name.len()) }
+            };
+            (quote! { jni::objects::JByteArray }, quote! { set_byte_array_region }, bytes)
+        }
+    };
+
+    let save_array = quote! {
+        let #array_name = unsafe { #array_type::from_raw(#name.as_raw()) };
+    };
+
+    let copy_back = quote! {
+        {
+            let mut env = rc_env.borrow_mut();
+            env.#set_region(&#array_name, 0, #values)
+                .expect("Could not copy the values back into the Kotlin array");
+        }
+    };
+
+    (save_array, copy_back)
+}
+
+fn rust_fn_call_from_jni_type(jni_type: &JniType, name: &String, is_borrow: bool, is_mutable: bool) -> String {
     match jni_type {
-        JniType::Int32 | JniType::Int64 | JniType::UInt64 | JniType::Float32 | JniType::Float64 | JniType::String | JniType::Boolean => { name.clone() }
-        JniType::Receiver(_) => { todo!() }
-        JniType::ByteArray => { format!("& {name}") }
-        JniType::CustomType(_) => { format!("&mut {name}") }
-        JniType::Void => { todo!() }
-        JniType::Option(ty) => { rust_fn_call_from_jni_type(ty, name) }
-        JniType::Interface(_) => format!("&mut {name}"),
-        JniType::Vec(_) => format!("&mut {name}"),
+        JniType::Int8 | JniType::Int16 | JniType::Int32 | JniType::Int64 |
+        JniType::UInt8 | JniType::UInt16 | JniType::UInt32 | JniType::UInt64 |
+        JniType::ISize | JniType::USize | JniType::Float32 | JniType::Float64 |
+        JniType::Boolean | JniType::Char => { name.clone() }
+        JniType::Receiver(_) => unreachable!("Receivers are not typed parameters"),
+        JniType::String | JniType::ByteArray | JniType::PrimitiveArray(_) | JniType::CustomType(_) if is_borrow && is_mutable => { format!("&mut {name}") }
+        JniType::String | JniType::ByteArray | JniType::PrimitiveArray(_) | JniType::CustomType(_) if is_borrow => { format!("& {name}") }
+        JniType::PrimitiveArray(_) => { name.clone() }
+        // Owned strings are converted into the type of the parameter, which can also be a `Cow<str>`
+        JniType::String => { format!("{name}.into()") }
+        JniType::ByteArray => { name.clone() }
+        JniType::CustomType(_) | JniType::Enum(_) => { name.clone() }
+        JniType::Option(_) => { name.clone() }
+        JniType::DirectBuffer => name.clone(),
+        JniType::Void | JniType::Result(_) | JniType::RustBuffer => {
+            unreachable!("Void, Result and Rust buffer parameters are rejected by the validation of the macro input")
+        }
+        JniType::Interface(_) if is_borrow => format!("&mut {name}"),
+        JniType::Interface(_) => name.clone(),
+        JniType::Vec(_) | JniType::HashSet(_) | JniType::BTreeSet(_) |
+        JniType::HashMap(..) | JniType::BTreeMap(..) | JniType::Tuple(_) if is_borrow => format!("& {name}"),
+        JniType::Vec(_) | JniType::HashSet(_) | JniType::BTreeSet(_) |
+        JniType::HashMap(..) | JniType::BTreeMap(..) | JniType::Tuple(_) => name.clone(),
     }
 }
 
@@ -150,19 +346,34 @@ fn jni_type_to_jni_type(jni_type: &JniType, is_optional: bool) -> TokenStream2 {
         quote! { jni::objects::JObject<'local> }
     } else {
         match jni_type {
+            JniType::Int8 => quote! { jni::sys::jbyte },
+            JniType::Int16 => quote! { jni::sys::jshort },
             JniType::Int32 => quote! { jni::sys::jint },
-            JniType::Int64 => quote! { jni::sys::jlong },
-            JniType::UInt64 => quote! { jni::sys::jlong }, // TODO This should be unsigned, perhaps use an object?
+            JniType::Int64 | JniType::ISize | JniType::USize => quote! { jni::sys::jlong },
+            JniType::UInt8 => quote! { jni::sys::jbyte },
+            JniType::UInt16 => quote! { jni::sys::jshort },
+            JniType::UInt32 => quote! { jni::sys::jint },
+            JniType::UInt64 => quote! { jni::sys::jlong },
             JniType::Float32 => quote! { jni::sys::jfloat },
             JniType::Float64 => quote! { jni::sys::jdouble },
             JniType::String => quote! { jni::objects::JString<'local> },
             JniType::Boolean => quote! { jni::sys::jboolean },
-            JniType::ByteArray => quote! { jni::objects::JByteArray },
-            JniType::Interface(_) | JniType::CustomType(_) => quote! { jni::objects::JObject<'local> },
+            JniType::Char => quote! { jni::sys::jint },
+            JniType::ByteArray => quote! { jni::objects::JByteArray<'local> },
+            JniType::PrimitiveArray(ty) => {
+                let array_type = jni_type_to_primitive_array(ty).array_type;
+                quote! { #array_type<'local> }
+            }
+            JniType::DirectBuffer => quote! { jni::objects::JByteBuffer<'local> },
+            JniType::RustBuffer => quote! { jni::objects::JObject<'local> },
+            JniType::Interface(_) | JniType::CustomType(_) | JniType::Enum(_) => quote! { jni::objects::JObject<'local> },
             JniType::Receiver(_) => quote! { jni::sys::jlong },
-            JniType::Void => todo!(),
-            JniType::Vec(_) => quote! { jni::sys::jarray },
+            JniType::Void => unreachable!("Void return types are removed when the function is parsed"),
+            JniType::Vec(_) => quote! { jni::objects::JObjectArray<'local> },
+            JniType::HashSet(_) | JniType::BTreeSet(_) |
+            JniType::HashMap(..) | JniType::BTreeMap(..) | JniType::Tuple(_) => quote! { jni::objects::JObject<'local> },
             JniType::Option(ty) => jni_type_to_jni_type(ty, true),
+            JniType::Result(ty) => jni_type_to_jni_type(ty, false),
         }
     }
 }
\ No newline at end of file
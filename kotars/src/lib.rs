@@ -3,35 +3,172 @@ extern crate proc_macro;
 use proc_macro::TokenStream;
 
 use quote::{quote, ToTokens};
-use syn::{FnArg, ImplItem, ItemImpl, ItemStruct, ItemTrait, LitStr, parse_macro_input, ReturnType, TraitItem};
+use syn::{FnArg, Ident, ImplItem, ItemEnum, ItemImpl, ItemStruct, ItemTrait, LitStr, parse_macro_input, ReturnType, TraitItem};
 use syn::__private::{str, TokenStream2};
 use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
 use syn::token::Comma;
 
-use kotars_common::{Field, Function, JniType, Parameter, RsInterface, RsStruct, string_to_camel_case};
+use kotars_common::{ClassSync, Field, Function, JniType, KOTARS_METADATA_DIR_ENV, Parameter, RsClass, RsInterface, RsStruct};
 use structs::JniGenerator;
 
+use crate::enums::{generate_enum_conversions, generate_sealed_class_conversions, rs_enum_from_syn, rs_sealed_class_from_syn};
 use crate::functions::generate_rust_jni_binding_functions;
 use crate::structs::{Class, DataClass, FromSyn};
-use crate::types_transformations::{transform_jni_type_to_rust, transform_rust_to_jni_type};
+use crate::validation::TypePosition;
+use crate::types_transformations::{transform_jni_type_to_rust, transform_jobject_to_unsynchronized_class, transform_jvalue_to_jni_type, transform_rust_to_jni_type};
 
+mod enums;
 mod functions;
 mod structs;
+mod type_resolver;
 mod types_transformations;
+mod validation;
 
+pub(crate) const DEFAULT_PANIC_EXCEPTION: &str = "java/lang/RuntimeException";
+pub(crate) const SIGNATURE_PACKAGE_PLACEHOLDER: &str = "<PKG_NAME>/";
 pub(crate) const AUTO_GENERATED_HEADER_TEXT: &str = "Auto generated header. This will be used by cargo-kotars to generate the Kotlin code that binds to the Rust code.";
 
 #[proc_macro]
 pub fn jni_init(input: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(input as LitStr);
+    let input = parse_macro_input!(input with Punctuated::<LitStr, Comma>::parse_terminated);
 
-    let package_name = quote! { #input };
+    if input.len() > 2 {
+        let err = syn::Error::new_spanned(&input[2], "jni_init! takes the package name and, optionally, the class of the exception thrown on panics");
+        return err.to_compile_error().into();
+    }
+
+    let mut input = input.iter();
+
+    let Some(package_name) = input.next() else {
+        let err = syn::Error::new(proc_macro2::Span::call_site(), "jni_init! requires the package name as first argument");
+        return err.to_compile_error().into();
+    };
+    let package_name = package_name.value();
     println!("Package name: {package_name}");
+    let package_name_record = format!("JNI_PACKAGE_NAME {}", serde_json::to_string(&package_name).unwrap());
+    let package_name_header = full_header_comment("jni_init!", &package_name_record);
+
+    let package_path = if package_name.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", package_name.replace('.', "/"))
+    };
+    let mangled_package_path = kotars_common::jni_mangle(&package_path);
+    let rust_tasks_class_path = format!("{package_path}RustTasks");
+    let rust_tasks_cancel = kotars_common::jni_native_method_name(&rust_tasks_class_path, "cancel", None);
+    let rust_tasks_release = kotars_common::jni_native_method_name(&rust_tasks_class_path, "release", None);
+    let rust_buffer_class_path = format!("{package_path}RustBuffer");
+    let rust_buffers_free = kotars_common::jni_native_method_name(&format!("{package_path}RustBuffers"), "free", None);
+
+    let panic_exception = input
+        .next()
+        .map(|exception| exception.value().replace('.', "/"))
+        .unwrap_or_else(|| DEFAULT_PANIC_EXCEPTION.to_string());
+
+    // Invalid aliases are reported here once, instead of wherever a type is resolved
+    let aliases_error = type_resolver::validate_aliases().err().map(|err| err.to_compile_error());
+
+    // The macros read the aliases of the manifest, so the crate is built again when it changes
+    let manifest_dependency = std::env::var_os("CARGO_MANIFEST_DIR").map(|_| {
+        quote! {
+            const _: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"));
+        }
+    });
 
     // todo move IntoEnv interface as part of the kotars crate instead of being generated
     let base_definition = quote! {
+        #package_name_header
+        #aliases_error
+        #manifest_dependency
         pub const JNI_PACKAGE_NAME: &str = #package_name;
+        pub const JNI_PACKAGE_PATH: &str = #package_path;
+        pub const JNI_PANIC_EXCEPTION: &str = #panic_exception;
+
+        // JNI functions are exported under the package of their Kotlin class. Export names can't be built
+        // from constants, so the bindings generated after this point get the mangled package from this macro
+        #[allow(unused_macros)]
+        macro_rules! jni_mangled_package_path {
+            () => { #mangled_package_path };
+        }
+
+        /// Replaces the package placeholder of the signatures generated by the macros
+        #[allow(dead_code)]
+        fn jni_signature(signature: &str) -> String {
+            signature.replace(#SIGNATURE_PACKAGE_PLACEHOLDER, JNI_PACKAGE_PATH)
+        }
 
+        static JNI_PANIC_HOOK: std::sync::Once = std::sync::Once::new();
+
+        thread_local! {
+            static JNI_PANIC_BACKTRACE: std::cell::RefCell<Option<std::backtrace::Backtrace>> = const { std::cell::RefCell::new(None) };
+        }
+
+        /// Unwinding payload of the values received from Kotlin that can't be converted, which is thrown as an
+        /// `IllegalArgumentException` instead of a `RustException` and skips the panic hook
+        struct JniIllegalArgument(String);
+
+        #[allow(dead_code)]
+        fn throw_illegal_argument(message: String) -> ! {
+            std::panic::resume_unwind(Box::new(JniIllegalArgument(message)))
+        }
+
+        fn catch_jni_panic<R: Default>(env: &mut jni::JNIEnv, function_name: &str, f: impl FnOnce() -> R) -> R {
+            JNI_PANIC_HOOK.call_once(|| {
+                let default_hook = std::panic::take_hook();
+                std::panic::set_hook(Box::new(move |info| {
+                    JNI_PANIC_BACKTRACE.with(|backtrace| {
+                        *backtrace.borrow_mut() = Some(std::backtrace::Backtrace::capture());
+                    });
+                    default_hook(info);
+                }));
+            });
+
+            match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+                Ok(result) => result,
+                Err(payload) if payload.is::<JniIllegalArgument>() => {
+                    let JniIllegalArgument(message) = *payload.downcast::<JniIllegalArgument>().unwrap();
+
+                    if env.exception_check().unwrap_or(false) {
+                        let _ = env.exception_clear();
+                    }
+                    let _ = env.throw_new("java/lang/IllegalArgumentException", message);
+
+                    R::default()
+                }
+                Err(payload) => {
+                    let panic_message = if let Some(message) = payload.downcast_ref::<&str>() {
+                        message.to_string()
+                    } else if let Some(message) = payload.downcast_ref::<String>() {
+                        message.clone()
+                    } else {
+                        "Unknown panic payload".to_string()
+                    };
+
+                    let mut message = format!("Rust function {function_name} panicked: {panic_message}");
+
+                    let backtrace = JNI_PANIC_BACKTRACE.with(|backtrace| backtrace.borrow_mut().take());
+                    if let Some(backtrace) = backtrace {
+                        if backtrace.status() == std::backtrace::BacktraceStatus::Captured {
+                            message = format!("{message}\n{backtrace}");
+                        }
+                    }
+
+                    // Panicking again here would abort the JVM, so failures to throw are ignored
+                    if env.exception_check().unwrap_or(false) {
+                        let _ = env.exception_clear();
+                    }
+                    let _ = env.throw_new(JNI_PANIC_EXCEPTION, message);
+
+                    R::default()
+                }
+            }
+        }
+
+        #[diagnostic::on_unimplemented(
+            message = "`{Self}` can't be converted into `{T}` by the generated bindings",
+            note = "types used by the item that are not supported are pointed out by their own errors",
+        )]
         trait IntoEnv<'a, T> {
             fn into_env(self, env: &mut std::cell::RefMut<'_, jni::JNIEnv<'a>>) -> T;
         }
@@ -53,6 +190,203 @@ pub fn jni_init(input: TokenStream) -> TokenStream {
             }
         }
 
+        #[allow(dead_code)]
+        fn throw_rust_exception(env: &mut jni::JNIEnv, message: String) {
+            let package_name_for_signature = JNI_PACKAGE_NAME.replace(".", "/");
+
+            let class_path = if package_name_for_signature.is_empty() {
+                "RustException".to_string()
+            } else {
+                format!("{}/RustException", package_name_for_signature)
+            };
+
+            env.throw_new(class_path, message).expect("Throwing RustException failed");
+        }
+
+        // Async functions are run by a pool of worker threads shared by every task, which is started
+        // with the first task and sized by the available parallelism. Workers poll the tasks woken by
+        // their waker, so a future woken from any thread is polled again by the first free worker.
+        // The Kotlin side gets a pointer to the task to cancel it, which drops the future on a worker,
+        // and to release it once the coroutine is done.
+        //
+        // The executor has no reactor, so futures relying on the one of a runtime like tokio or
+        // async-std hang or panic when polled here. Those have to be spawned on their runtime and
+        // their join handle awaited instead, e.g. `handle.spawn(future).await`. Futures blocking the
+        // thread they are polled by hold one of the workers until they are done.
+        struct JniTask {
+            future: std::sync::Mutex<Option<std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>>>,
+            scheduled: std::sync::atomic::AtomicBool,
+            cancelled: std::sync::atomic::AtomicBool,
+        }
+
+        struct JniExecutor {
+            queue: std::sync::Mutex<std::collections::VecDeque<std::sync::Arc<JniTask>>>,
+            available: std::sync::Condvar,
+        }
+
+        static JNI_EXECUTOR: std::sync::OnceLock<JniExecutor> = std::sync::OnceLock::new();
+
+        fn jni_executor() -> &'static JniExecutor {
+            JNI_EXECUTOR.get_or_init(|| {
+                let workers = std::thread::available_parallelism().map_or(2, |workers| workers.get().max(2));
+
+                for index in 0..workers {
+                    std::thread::Builder::new()
+                        .name(format!("kotars-worker-{index}"))
+                        .spawn(run_jni_worker)
+                        .expect("Could not spawn a worker of the kotars executor");
+                }
+
+                JniExecutor {
+                    queue: std::sync::Mutex::new(std::collections::VecDeque::new()),
+                    available: std::sync::Condvar::new(),
+                }
+            })
+        }
+
+        fn run_jni_worker() {
+            let executor = jni_executor();
+
+            loop {
+                let task = {
+                    let mut queue = executor.queue.lock().unwrap();
+                    loop {
+                        match queue.pop_front() {
+                            Some(task) => break task,
+                            None => queue = executor.available.wait(queue).unwrap(),
+                        }
+                    }
+                };
+
+                // Wakes received while the task is polled schedule it again
+                task.scheduled.store(false, std::sync::atomic::Ordering::SeqCst);
+
+                let mut future = task.future.lock().unwrap();
+                if task.cancelled.load(std::sync::atomic::Ordering::SeqCst) {
+                    *future = None;
+                    continue;
+                }
+
+                let Some(pending_future) = future.as_mut() else {
+                    continue;
+                };
+
+                let waker = std::task::Waker::from(std::sync::Arc::clone(&task));
+                let mut context = std::task::Context::from_waker(&waker);
+                let poll = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| pending_future.as_mut().poll(&mut context)));
+
+                // Panics are reported by the future spawned by `spawn_jni_task`, so any other one only ends the task
+                if !matches!(poll, Ok(std::task::Poll::Pending)) {
+                    *future = None;
+                }
+            }
+        }
+
+        impl std::task::Wake for JniTask {
+            fn wake(self: std::sync::Arc<Self>) {
+                self.wake_by_ref();
+            }
+
+            fn wake_by_ref(self: &std::sync::Arc<Self>) {
+                if !self.scheduled.swap(true, std::sync::atomic::Ordering::SeqCst) {
+                    let executor = jni_executor();
+                    executor.queue.lock().unwrap().push_back(std::sync::Arc::clone(self));
+                    executor.available.notify_one();
+                }
+            }
+        }
+
+        /// Runs the future on the executor until it's done or the task is cancelled
+        #[allow(dead_code)]
+        fn spawn_jni_executor_task<F: std::future::Future<Output = ()> + Send + 'static>(future: F) -> std::sync::Arc<JniTask> {
+            let task = std::sync::Arc::new(JniTask {
+                future: std::sync::Mutex::new(Some(Box::pin(future))),
+                scheduled: std::sync::atomic::AtomicBool::new(false),
+                cancelled: std::sync::atomic::AtomicBool::new(false),
+            });
+            std::task::Wake::wake_by_ref(&task);
+
+            task
+        }
+
+        /// Drops the future of the task on a worker, without waiting for it
+        #[allow(dead_code)]
+        fn cancel_jni_executor_task(task: &std::sync::Arc<JniTask>) {
+            task.cancelled.store(true, std::sync::atomic::Ordering::SeqCst);
+            std::task::Wake::wake_by_ref(task);
+        }
+
+        #[allow(dead_code)]
+        fn spawn_jni_task<F, C>(
+            env: &mut jni::JNIEnv,
+            completable_future: jni::objects::JObject,
+            function_name: &'static str,
+            future: F,
+            complete: C,
+        ) -> jni::sys::jlong
+        where
+            F: std::future::Future + Send + 'static,
+            C: for<'a> FnOnce(jni::JNIEnv<'a>, &jni::objects::JObject, F::Output) + Send + 'static,
+        {
+            let vm = env.get_java_vm().expect("Could not get the Java VM");
+            let completable_future = env.new_global_ref(completable_future).expect("Could not create a global reference of the CompletableFuture");
+            let mut future = Box::pin(future);
+            let mut complete = Some(complete);
+
+            // Workers stay attached to the JVM, so the local references of each poll are freed by a frame of their own
+            let task = spawn_jni_executor_task(std::future::poll_fn(move |context| {
+                let mut env = vm.attach_current_thread_as_daemon().expect("Could not attach the worker thread to the JVM");
+
+                env.with_local_frame(16, |env| -> jni::errors::Result<std::task::Poll<()>> {
+                    let mut panic_env = unsafe { env.unsafe_clone() };
+                    let completable_future = completable_future.as_obj();
+
+                    let is_ready = catch_jni_panic(&mut panic_env, function_name, || match future.as_mut().poll(context) {
+                        std::task::Poll::Ready(output) => {
+                            let complete = complete.take().expect("The task was polled after it completed");
+                            complete(unsafe { env.unsafe_clone() }, completable_future, output);
+                            true
+                        }
+                        std::task::Poll::Pending => false,
+                    });
+
+                    // Errors and panics are thrown as exceptions, which fail the CompletableFuture instead
+                    if env.exception_check().unwrap_or(false) {
+                        let throwable = env.exception_occurred().expect("Could not get the thrown exception");
+                        env.exception_clear().expect("Could not clear the thrown exception");
+                        env.call_method(completable_future, "completeExceptionally", "(Ljava/lang/Throwable;)Z", &[(&throwable).into()])
+                            .expect("Could not complete the CompletableFuture exceptionally");
+
+                        return Ok(std::task::Poll::Ready(()));
+                    }
+
+                    Ok(if is_ready { std::task::Poll::Ready(()) } else { std::task::Poll::Pending })
+                })
+                .expect("Could not create the local frame of the task")
+            }));
+
+            std::sync::Arc::into_raw(task) as jni::sys::jlong
+        }
+
+        #[export_name = #rust_tasks_cancel]
+        pub extern "system" fn jni_rust_tasks_cancel<'local>(
+            _env: jni::JNIEnv<'local>,
+            _object: jni::objects::JObject<'local>,
+            task: jni::sys::jlong,
+        ) {
+            let task = std::mem::ManuallyDrop::new(unsafe { std::sync::Arc::from_raw(task as *const JniTask) });
+            cancel_jni_executor_task(&task);
+        }
+
+        #[export_name = #rust_tasks_release]
+        pub extern "system" fn jni_rust_tasks_release<'local>(
+            _env: jni::JNIEnv<'local>,
+            _object: jni::objects::JObject<'local>,
+            task: jni::sys::jlong,
+        ) {
+            drop(unsafe { std::sync::Arc::from_raw(task as *const JniTask) });
+        }
+
         impl IntoEnv<'_, Vec<u8>> for jni::objects::JByteArray<'_> {
             fn into_env(self, env: &mut std::cell::RefMut<'_, jni::JNIEnv>) -> Vec<u8> {
                 env.convert_byte_array(self).unwrap()
@@ -64,14 +398,168 @@ pub fn jni_init(input: TokenStream) -> TokenStream {
                 output
             }
         }
+        /// Bytes shared with the JVM without copying them. Parameters borrowing it receive the remaining bytes of a
+        /// direct `ByteBuffer`, and functions returning a `Box<DirectBuffer>` hand its memory over to a Kotlin
+        /// `RustBuffer`. Plain `&[u8]` and `&mut [u8]` parameters keep taking a copied Kotlin `ByteArray`, so direct
+        /// buffers are opted into by naming this alias, which the macros tell apart from the slice it stands for.
+        #[allow(dead_code)]
+        pub type DirectBuffer = [u8];
+
+        impl <'local> IntoEnv<'local, jni::objects::JObject<'local>> for Box<DirectBuffer> {
+            fn into_env(self, env: &mut std::cell::RefMut<'_, jni::JNIEnv<'local>>) -> jni::objects::JObject<'local> {
+                let capacity = self.len();
+                assert!(capacity <= jni::sys::jint::MAX as usize, "Buffers returned to the JVM can't be larger than {} bytes", jni::sys::jint::MAX);
+
+                let pointer = Box::into_raw(self) as *mut u8;
+                let buffer = unsafe { env.new_direct_byte_buffer(pointer, capacity) }
+                    .expect("Could not create the direct ByteBuffer");
+
+                let constructor_args: &[jni::objects::JValue] = &[(&buffer).into(), (pointer as jni::sys::jlong).into()];
+                env.new_object(#rust_buffer_class_path, "(Ljava/nio/ByteBuffer;J)V", constructor_args)
+                    .expect("Could not create the RustBuffer")
+            }
+        }
+
+        #[export_name = #rust_buffers_free]
+        pub extern "system" fn jni_rust_buffers_free<'local>(
+            _env: jni::JNIEnv<'local>,
+            _object: jni::objects::JObject<'local>,
+            pointer: jni::sys::jlong,
+            capacity: jni::sys::jint,
+        ) {
+            let buffer = std::ptr::slice_from_raw_parts_mut(pointer as *mut u8, capacity as usize);
+            drop(unsafe { Box::from_raw(buffer) });
+        }
+
+        impl <'local> IntoEnv<'local, jni::objects::JByteArray<'local>> for &[u8] {
+            fn into_env(self, env: &mut std::cell::RefMut<'_, jni::JNIEnv<'local>>) -> jni::objects::JByteArray<'local> {
+                env.byte_array_from_slice(self).unwrap()
+            }
+        }
+
+        // Implemented by the types that can be converted from Kotlin values, which excludes #[jni_class] structs
+        #[allow(dead_code)]
+        #[diagnostic::on_unimplemented(
+            message = "`{Self}` can't be received from Kotlin by value",
+            label = "not a #[jni_data_class], #[jni_enum] or #[jni_sealed_class]",
+            note = "the Kotlin object of a #[jni_class] keeps ownership of its instance, so Rust can't take the handle by value, alone or in a collection like `Vec`. Handles are returned to Kotlin by value, alone or in collections, but received by borrowing them as a `&` or `&mut` parameter",
+        )]
+        trait JniValue {}
+
+        // Implemented by the bridges of the interfaces declared with #[jni_interface(persistent)], whose
+        // global bridge keeps the Kotlin object alive so functions can take the interface by value
+        #[allow(dead_code)]
+        #[diagnostic::on_unimplemented(
+            message = "interface taken by value must be declared with #[jni_interface(persistent)]",
+            label = "`{Self}` is the bridge of an interface that isn't persistent",
+            note = "the Kotlin object of other interfaces only lives as long as the call, so functions can only borrow them as `&impl Trait`",
+        )]
+        trait JniPersistentInterface {
+            type GlobalBridge;
+
+            fn global_bridge(env: &jni::JNIEnv, callback: &jni::objects::JObject) -> Self::GlobalBridge;
+        }
+
+        // Instances of #[jni_class] structs are boxed in the cell chosen by their `sync` argument, which the
+        // functions lock for reading when they borrow the instance and for writing when they borrow it mutably
+        #[allow(dead_code)]
+        #[diagnostic::on_unimplemented(
+            message = "`{Self}` can't be borrowed from Kotlin",
+            label = "not a #[jni_class]",
+            note = "only instances of #[jni_class] structs are owned by their Kotlin object, other types must be taken by value",
+        )]
+        trait JniClass: Sized {
+            type Cell: JniClassCell<Value = Self>;
+        }
+
+        #[allow(dead_code)]
+        trait JniClassCell {
+            type Value;
+
+            fn new(value: Self::Value) -> Self;
+            fn read(&self) -> impl std::ops::Deref<Target = Self::Value> + '_;
+            fn write(&self) -> impl std::ops::DerefMut<Target = Self::Value> + '_;
+        }
+
+        /// Cell of the classes with `sync = "none"`, which leave the synchronization to the Kotlin side
+        #[allow(dead_code)]
+        struct UnsynchronizedCell<T>(std::cell::UnsafeCell<T>);
+
+        impl<T> JniClassCell for UnsynchronizedCell<T> {
+            type Value = T;
+
+            fn new(value: T) -> Self {
+                UnsynchronizedCell(std::cell::UnsafeCell::new(value))
+            }
+
+            fn read(&self) -> impl std::ops::Deref<Target = T> + '_ {
+                unsafe { &*self.0.get() }
+            }
+
+            fn write(&self) -> impl std::ops::DerefMut<Target = T> + '_ {
+                unsafe { &mut *self.0.get() }
+            }
+        }
+
+        // Panics are thrown as exceptions and leave the instance usable, so poisoned locks are still taken
+        impl<T> JniClassCell for std::sync::Mutex<T> {
+            type Value = T;
+
+            fn new(value: T) -> Self {
+                std::sync::Mutex::new(value)
+            }
+
+            fn read(&self) -> impl std::ops::Deref<Target = T> + '_ {
+                self.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+            }
+
+            fn write(&self) -> impl std::ops::DerefMut<Target = T> + '_ {
+                self.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+            }
+        }
+
+        impl<T> JniClassCell for std::sync::RwLock<T> {
+            type Value = T;
+
+            fn new(value: T) -> Self {
+                std::sync::RwLock::new(value)
+            }
+
+            fn read(&self) -> impl std::ops::Deref<Target = T> + '_ {
+                std::sync::RwLock::read(self).unwrap_or_else(std::sync::PoisonError::into_inner)
+            }
+
+            fn write(&self) -> impl std::ops::DerefMut<Target = T> + '_ {
+                std::sync::RwLock::write(self).unwrap_or_else(std::sync::PoisonError::into_inner)
+            }
+        }
+
+        /// Instance of an unsynchronized class owned by a Kotlin object, borrowed without a guard by the interfaces
+        /// returning it. Classes synchronized by a lock can't be borrowed past the function taking the lock.
+        /// The reference is bound to the borrow of the bridge that returned it, so it can't outlive the callback,
+        /// but the Kotlin object owning the instance must still not be closed while it's used.
+        #[allow(dead_code)]
+        unsafe fn unsynchronized_class<'s, O: ?Sized, T: JniClass<Cell = UnsynchronizedCell<T>>>(
+            _bridge: &'s O,
+            pointer: jni::sys::jlong,
+        ) -> &'s T {
+            &*(*(pointer as *const UnsynchronizedCell<T>)).0.get()
+        }
     };
 
     base_definition.into()
 }
 
+/// Exposes the functions of the impl block to Kotlin. `async fn`s become suspend functions run by a thread per
+/// call that only wakes up through the waker of the future, so futures needing the reactor of a runtime like
+/// tokio must be spawned on that runtime and their join handle awaited instead.
 #[proc_macro_attribute]
 pub fn jni_struct_impl(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let input_impl = parse_macro_input!(item as ItemImpl);
+    if let Err(err) = validation::validate_struct_impl(&input_impl) {
+        return invalid_item_output(&input_impl, err);
+    }
+
     let struct_name = input_impl.self_ty.as_ref().to_token_stream().to_string();
 
     let functions = input_impl
@@ -82,47 +570,168 @@ pub fn jni_struct_impl(_attr: TokenStream, item: TokenStream) -> TokenStream {
                 let method_name = &method.sig.ident;
                 let parameters = get_parameters_from_method(&method.sig.inputs);
                 let return_type = get_return_type_from_method(&method.sig.output);
+                let parameter_spans = method.sig.inputs
+                    .iter()
+                    .map(|input| match input {
+                        // Interfaces are pointed at by their trait, the first bound of the `impl`
+                        FnArg::Typed(pat_type) => match pat_type.ty.as_ref() {
+                            syn::Type::ImplTrait(impl_trait) => impl_trait.bounds.span(),
+                            ty => ty.span(),
+                        },
+                        FnArg::Receiver(receiver) => receiver.span(),
+                    })
+                    .collect::<Vec<proc_macro2::Span>>();
 
-                Some(Function {
+                let function = Function {
                     owner_name: struct_name.clone(),
                     name: method_name.to_string(),
                     parameters,
                     return_type,
-                })
+                    is_async: method.sig.asyncness.is_some(),
+                };
+
+                Some((function, parameter_spans))
             } else {
                 None
             }
         })
-        .collect::<Vec<Function>>();
+        .collect::<Vec<(Function, Vec<proc_macro2::Span>)>>();
 
     let new_functions = generate_rust_jni_binding_functions(&struct_name, &functions);
 
+    let parameter_types = input_impl.items
+        .iter()
+        .filter_map(|item| match item {
+            ImplItem::Fn(method) => Some(&method.sig.inputs),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|input| match input {
+            FnArg::Typed(pat_type) => Some((pat_type.ty.as_ref(), TypePosition::FunctionParameter)),
+            FnArg::Receiver(_) => None,
+        });
+    let type_assertions = validation::type_assertions(parameter_types);
+
     let output = quote! {
         #input_impl
         #(#new_functions)*
+        #type_assertions
     };
 
     output.into()
 }
 
 #[proc_macro_attribute]
-pub fn jni_class(_attr: TokenStream, input: TokenStream) -> TokenStream {
+pub fn jni_class(attr: TokenStream, input: TokenStream) -> TokenStream {
     let item_struct = parse_macro_input!(input as ItemStruct);
 
-    let rs_struct = RsStruct::from_syn(item_struct.clone());
-    let class: Class = rs_struct.into();
+    let sync = match (parse_class_sync(attr), validation::validate_class(&item_struct)) {
+        (Ok(sync), Ok(())) => sync,
+        (Err(mut err), Err(validation_err)) => {
+            err.combine(validation_err);
+            return invalid_item_output(&item_struct, err);
+        }
+        (Err(err), _) | (_, Err(err)) => return invalid_item_output(&item_struct, err),
+    };
+
+    // Fields of classes stay in Rust, so they are not part of the binding
+    let rs_class = RsClass {
+        name: item_struct.ident.to_string(),
+        sync,
+    };
+    let class: Class = rs_class.into();
 
     jni_class_generator(item_struct, &class)
 }
 
+/// Parses the `sync = "mutex" | "rwlock" | "none"` argument of #[jni_class], which defaults to `none`
+fn parse_class_sync(attr: TokenStream) -> syn::Result<ClassSync> {
+    let mut sync = ClassSync::None;
+
+    let parser = syn::meta::parser(|meta| {
+        if !meta.path.is_ident("sync") {
+            return Err(meta.error("unknown #[jni_class] argument, the only supported argument is `sync`"));
+        }
+
+        let mode: LitStr = meta.value()?.parse()?;
+        sync = match mode.value().as_str() {
+            "none" => ClassSync::None,
+            "mutex" => ClassSync::Mutex,
+            "rwlock" => ClassSync::RwLock,
+            other => {
+                return Err(syn::Error::new_spanned(
+                    &mode,
+                    format!("unknown #[jni_class] sync mode `{other}`, the supported modes are `mutex`, `rwlock` and `none`"),
+                ));
+            }
+        };
+
+        Ok(())
+    });
+    syn::parse::Parser::parse(parser, attr)?;
+
+    Ok(sync)
+}
+
 #[proc_macro_attribute]
 pub fn jni_data_class(_attr: TokenStream, input: TokenStream) -> TokenStream {
     let item_struct = parse_macro_input!(input as ItemStruct);
+    if let Err(err) = validation::validate_data_class(&item_struct) {
+        return invalid_item_output(&item_struct, err);
+    }
+
+    let type_assertions = validation::type_assertions(fields_types(&item_struct.fields, TypePosition::DataClassField));
 
     let rs_struct = RsStruct::from_syn(item_struct.clone());
     let data_class: DataClass = rs_struct.into();
 
-    jni_class_generator(item_struct, &data_class)
+    let out = TokenStream2::from(jni_class_generator(item_struct, &data_class));
+    quote! { #out #type_assertions }.into()
+}
+
+#[proc_macro_attribute]
+pub fn jni_enum(_attr: TokenStream, input: TokenStream) -> TokenStream {
+    let item_enum = parse_macro_input!(input as ItemEnum);
+    if let Err(err) = validation::validate_enum(&item_enum) {
+        return invalid_item_output(&item_enum, err);
+    }
+
+    let rs_enum = rs_enum_from_syn(&item_enum);
+    let conversions = generate_enum_conversions(&rs_enum);
+
+    let out = quote! {
+        #item_enum
+
+        #conversions
+    };
+
+    out.into()
+}
+
+#[proc_macro_attribute]
+pub fn jni_sealed_class(_attr: TokenStream, input: TokenStream) -> TokenStream {
+    let item_enum = parse_macro_input!(input as ItemEnum);
+    if let Err(err) = validation::validate_sealed_class(&item_enum) {
+        return invalid_item_output(&item_enum, err);
+    }
+
+    let type_assertions = validation::type_assertions(
+        item_enum.variants
+            .iter()
+            .flat_map(|variant| fields_types(&variant.fields, TypePosition::SealedClassField)),
+    );
+
+    let rs_sealed_class = rs_sealed_class_from_syn(&item_enum);
+    let conversions = generate_sealed_class_conversions(&rs_sealed_class);
+
+    let out = quote! {
+        #item_enum
+
+        #conversions
+        #type_assertions
+    };
+
+    out.into()
 }
 
 fn jni_class_generator(item_struct: ItemStruct, jni_generator: &impl JniGenerator) -> TokenStream {
@@ -137,29 +746,111 @@ fn jni_class_generator(item_struct: ItemStruct, jni_generator: &impl JniGenerato
     out.into()
 }
 
-pub(crate) fn full_header_comment(content: &str) -> TokenStream2 {
-    let header_comments = format!(
-        r#"
-        /// JNI_BINDING_START
-        /// {content}
-        /// JNI_BINDING_END
-        /// {AUTO_GENERATED_HEADER_TEXT}
-        "#
-    )
-        .trim_start()
-        .to_string();
+fn fields_types(fields: &syn::Fields, position: TypePosition) -> impl Iterator<Item = (&syn::Type, TypePosition)> {
+    fields.iter().map(move |field| (&field.ty, position))
+}
+
+/// Keeps the item given to a macro when its input is invalid, so its errors don't cascade into unresolved names
+fn invalid_item_output(item: &impl ToTokens, err: syn::Error) -> TokenStream {
+    let errors = err.to_compile_error();
+
+    let out = quote! {
+        #item
+        #errors
+    };
+
+    out.into()
+}
+
+pub(crate) fn full_header_comment(item: &str, content: &str) -> TokenStream2 {
+    let block_lines = kotars_common::binding_block_lines(item, content);
+    let block = block_lines.join("\n");
+
+    write_metadata(&block);
+    let metadata_section = metadata_section(&block);
+
+    let doc_lines = block_lines
+        .iter()
+        .map(|line| format!(" {line}"))
+        .chain(std::iter::once(format!(" {AUTO_GENERATED_HEADER_TEXT}")));
 
-    syn::parse_str(&header_comments).unwrap()
+    quote! {
+        #metadata_section
+        #(#[doc = #doc_lines])*
+    }
+}
+
+/// Embeds a metadata block in the compiled library, so cargo-kotars can generate the Kotlin code from the
+/// binary without the source code.
+fn metadata_section(content: &str) -> TokenStream2 {
+    let macho_section = format!("__DATA,{}", kotars_common::METADATA_SECTION_MACHO);
+    let elf_section = kotars_common::METADATA_SECTION_ELF;
+    let pe_section = kotars_common::METADATA_SECTION_PE;
+
+    // Blocks are separated by NUL bytes
+    let mut record = content.as_bytes().to_vec();
+    record.push(0);
+    let record_len = record.len();
+    let record = proc_macro2::Literal::byte_string(&record);
+
+    quote! {
+        const _: () = {
+            #[used]
+            #[cfg_attr(any(target_os = "macos", target_os = "ios"), link_section = #macho_section)]
+            #[cfg_attr(windows, link_section = #pe_section)]
+            #[cfg_attr(not(any(target_os = "macos", target_os = "ios", windows)), link_section = #elf_section)]
+            static KOTARS_METADATA: [u8; #record_len] = *#record;
+        };
+    }
+}
+
+/// Writes a metadata block for cargo-kotars, which can't read the header comments without expanding the
+/// source code on a nightly toolchain. Dependencies using kotars are checked too, so only the records of the
+/// package being checked are written.
+fn write_metadata(content: &str) {
+    static RECORDS_COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    let Some(metadata_dir) = std::env::var_os(KOTARS_METADATA_DIR_ENV) else {
+        return;
+    };
+
+    if std::env::var_os("CARGO_PRIMARY_PACKAGE").is_none() {
+        return;
+    }
+
+    let metadata_dir = std::path::PathBuf::from(metadata_dir);
+    std::fs::create_dir_all(&metadata_dir).expect("Creating kotars metadata directory failed");
+
+    let index = RECORDS_COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    let file = metadata_dir.join(format!("{}-{index:08}.kotars", std::process::id()));
+    std::fs::write(&file, content).unwrap_or_else(|err| panic!("Writing kotars metadata {file:?} failed. {err}"));
 }
 
 #[proc_macro_attribute]
-pub fn jni_interface(_attr: TokenStream, input: TokenStream) -> TokenStream {
-    let item_trait = parse_macro_input!(input as ItemTrait);
+pub fn jni_interface(attr: TokenStream, input: TokenStream) -> TokenStream {
+    let mode = parse_macro_input!(attr as Option<Ident>);
+    let mut item_trait = parse_macro_input!(input as ItemTrait);
+
+    let is_persistent = match mode {
+        None => Ok(false),
+        Some(mode) if mode == "persistent" => Ok(true),
+        Some(mode) => Err(syn::Error::new_spanned(&mode, format!("unknown #[jni_interface] mode `{mode}`, the only supported mode is `persistent`"))),
+    };
+    let is_persistent = match (is_persistent, validation::validate_interface(&item_trait)) {
+        (Ok(is_persistent), Ok(())) => is_persistent,
+        (Err(mut err), Err(validation_err)) => {
+            err.combine(validation_err);
+            return invalid_item_output(&item_trait, err);
+        }
+        (Err(err), _) | (_, Err(err)) => return invalid_item_output(&item_trait, err),
+    };
     let trait_name = item_trait.ident.to_string();
-    let trait_token = &item_trait.ident;
+    let trait_token = item_trait.ident.clone();
 
     let trait_implementer_name = format!("{trait_name}JniBridge");
     let trait_implementer_name = syn::parse_str::<TokenStream2>(&trait_implementer_name).unwrap();
+    let global_trait_implementer_name = format!("{trait_name}GlobalJniBridge");
+    let global_trait_implementer_name = syn::parse_str::<TokenStream2>(&global_trait_implementer_name).unwrap();
 
     let functions = item_trait
         .items
@@ -170,13 +861,20 @@ pub fn jni_interface(_attr: TokenStream, input: TokenStream) -> TokenStream {
                 let return_type = &func.sig.output;
 
                 let inputs = &func.sig.inputs;
-                let str_method_name = string_to_camel_case(method_name.to_string().as_str());
+                let function = Function {
+                    owner_name: trait_name.clone(),
+                    name: method_name.to_string(),
+                    parameters: get_parameters_from_method(inputs),
+                    return_type: get_return_type_from_method(return_type),
+                    is_async: func.sig.asyncness.is_some(),
+                };
+                let str_method_name = function.interface_jni_method_name();
 
                 let return_type_signature = match return_type {
                     ReturnType::Default => { structs::jni_type_to_jni_method_signature_type(&JniType::Void) }
                     ReturnType::Type(_, ty) => {
-                        let jni_ty = quote::quote!(#ty).to_string().into();
-                        structs::jni_type_to_jni_method_signature_type(&jni_ty)
+                        let (jni_ty, _, _) = borrowed_jni_type(ty);
+                        kotlin_jni_method_signature_type(&jni_ty)
                     }
                 };
 
@@ -187,9 +885,8 @@ pub fn jni_interface(_attr: TokenStream, input: TokenStream) -> TokenStream {
                                 None
                             }
                             FnArg::Typed(pat_ty) => {
-                                let ty = &pat_ty.ty;
-                                let jni_ty = quote::quote!(#ty).to_string().into();
-                                Some(structs::jni_type_to_jni_method_signature_type(&jni_ty))
+                                let (jni_ty, _, _) = borrowed_jni_type(&pat_ty.ty);
+                                Some(kotlin_jni_method_signature_type(&jni_ty))
                             }
                         }
                     })
@@ -207,9 +904,18 @@ pub fn jni_interface(_attr: TokenStream, input: TokenStream) -> TokenStream {
                             FnArg::Typed(pat_ty) => {
                                 let pat = &pat_ty.pat;
                                 let name = quote! { #pat }.to_string();
-                                let original_ty = &pat_ty.ty;
-                                let ty = quote! { #original_ty }.to_string();
-                                let jni_ty: JniType = ty.into();
+                                let (jni_ty, is_borrow, _) = borrowed_jni_type(&pat_ty.ty);
+                                let param: TokenStream2 = syn::parse_str(&name).unwrap();
+
+                                // Borrowed values are copied before being handed over to the JVM. Interfaces are
+                                // passed as the Kotlin object they wrap, so they don't need ownership
+                                let owned_param = if is_borrow && !matches!(jni_ty, JniType::Interface(_)) {
+                                    Some(quote! { let #param = #param.to_owned(); })
+                                } else if is_cow(&pat_ty.ty) {
+                                    Some(quote! { let #param = #param.into_owned(); })
+                                } else {
+                                    None
+                                };
 
                                 let field = Field {
                                     is_public: true,
@@ -217,43 +923,39 @@ pub fn jni_interface(_attr: TokenStream, input: TokenStream) -> TokenStream {
                                     ty: jni_ty,
                                 };
 
-                                Some(field)
+                                Some((field, owned_param))
                             }
                         }
                     })
-                    .collect::<Vec<Field>>();
+                    .collect::<Vec<(Field, Option<TokenStream2>)>>();
+
+                let (fields, owned_params): (Vec<Field>, Vec<Option<TokenStream2>>) = fields.into_iter().unzip();
 
                 let transformations = structs::generate_method_fields_transformation(&fields);
                 let params_into_array = structs::generate_struct_fields_mapping_into_array(&fields);
-                let result_transformation = {
-                    match return_type {
-                        ReturnType::Default => {
+                let result_transformation = match return_type {
+                    ReturnType::Default => {
+                        quote! {
+                            let r = ();
+                        }
+                    }
+                    ReturnType::Type(_, ty) => {
+                        let (jni_ty, is_borrow, _) = borrowed_jni_type(ty);
+
+                        if matches!(jni_ty, JniType::Void) {
                             quote! {
                                 let r = ();
                             }
-                        }
-                        ReturnType::Type(_, ty) => {
-                            let jni_ty: JniType = quote::quote!(#ty).to_string().into();
-
-                            let jni_ty_transformation = transform_jni_type_to_rust(&jni_ty, "r", false);
-                            let jvalue_transformation = match jni_ty {
-                                JniType::Int32 => quote! { r.i() },
-                                JniType::Int64 => quote! { r.j() },
-                                JniType::Float32 => quote! { r.f() },
-                                JniType::Float64 => quote! { r.d() },
-                                JniType::Boolean => quote! { r.z() },
-                                JniType::UInt64 | JniType::Receiver(_) |
-                                JniType::CustomType(_) |
-                                JniType::Interface(_) |
-                                JniType::String |
-                                JniType::Option(_) |
-                                JniType::ByteArray => todo!(),
-                                JniType::Void => quote! { () },
-                                JniType::Vec(_) => todo!(),
+                        } else {
+                            // Borrowed classes are returned as a reference to the instance owned by the Kotlin object
+                            let jvalue_transformation = transform_jvalue_to_jni_type(&jni_ty, "r");
+                            let jni_ty_transformation = match &jni_ty {
+                                JniType::CustomType(class_name) if is_borrow => transform_jobject_to_unsynchronized_class("r", class_name),
+                                jni_ty => transform_jni_type_to_rust(jni_ty, "r", is_borrow),
                             };
 
                             quote! {
-                                let r = #jvalue_transformation.unwrap() ;
+                                #jvalue_transformation
                                 #jni_ty_transformation
                             }
                         }
@@ -262,35 +964,51 @@ pub fn jni_interface(_attr: TokenStream, input: TokenStream) -> TokenStream {
 
                 let error_msg = format!("Call method [{str_method_name}] with signature [{method_types_signature}] failed with error: {{e}}");
 
-                let q = quote! {
-                    fn #method_name(#inputs) #return_type {
-                        let rc_env = &self.env;
+                let method_body = quote! {
+                    #(#owned_params)*
+                    #(#transformations)*
 
-                        #(#transformations)*
+                    let method_args: &[jni::objects::JValue] = &[#(#params_into_array,)*];
 
-                        let method_args: &[jni::objects::JValue] = &[#(#params_into_array,)*];
+                    let r = {
+                        let mut env = rc_env.borrow_mut();
+                        env.call_method(callback, #str_method_name, crate::jni_signature(#method_types_signature), method_args)
+                            .unwrap_or_else(|e| panic!(#error_msg))
+                    };
 
-                        let result = {
-                            let mut env = rc_env.borrow_mut();
-                            let r = env.call_method(&self.callback, #str_method_name, #method_types_signature, method_args);
+                    #result_transformation
 
-                            let r = r.unwrap_or_else(|e| panic!(#error_msg));
+                    r
+                };
 
-                            #result_transformation
+                let method = quote! {
+                    fn #method_name(#inputs) #return_type {
+                        let rc_env = &self.env;
+                        let callback = self.callback.as_ref();
 
-                            r
-                        };
+                        #method_body
+                    }
+                };
 
-                        result
+                let global_method = quote! {
+                    fn #method_name(#inputs) #return_type {
+                        let attach_guard = self.vm
+                            .attach_current_thread()
+                            .expect("Could not attach the current thread to the JVM");
+                        let rc_env = std::rc::Rc::new(std::cell::RefCell::new(unsafe { attach_guard.unsafe_clone() }));
+                        let callback = self.callback.as_obj();
+
+                        #method_body
                     }
                 };
 
-                Some(q)
+                Some((method, global_method))
             } else {
                 None
             }
         })
-        .collect::<Vec<TokenStream2>>();
+        .unzip::<TokenStream2, TokenStream2, Vec<TokenStream2>, Vec<TokenStream2>>();
+    let (functions, global_functions) = functions;
 
     let functions_to_serialize = item_trait
         .items
@@ -306,6 +1024,7 @@ pub fn jni_interface(_attr: TokenStream, input: TokenStream) -> TokenStream {
                     name: method_name.to_string(),
                     parameters,
                     return_type,
+                    is_async: method.sig.asyncness.is_some(),
                 })
             } else {
                 None
@@ -313,6 +1032,17 @@ pub fn jni_interface(_attr: TokenStream, input: TokenStream) -> TokenStream {
         })
         .collect::<Vec<Function>>();
 
+    let return_types = item_trait.items
+        .iter()
+        .filter_map(|item| match item {
+            TraitItem::Fn(method) => match &method.sig.output {
+                ReturnType::Type(_, ty) => Some((ty.as_ref(), TypePosition::InterfaceReturn)),
+                ReturnType::Default => None,
+            },
+            _ => None,
+        });
+    let type_assertions = validation::type_assertions(return_types);
+
     let interface = RsInterface {
         name: trait_name,
         functions: functions_to_serialize,
@@ -321,11 +1051,20 @@ pub fn jni_interface(_attr: TokenStream, input: TokenStream) -> TokenStream {
     let interface_json = serde_json::to_string(&interface).unwrap();
 
     let header_param = format!("JNI_INTERFACE {interface_json}");
-    let header_comments = full_header_comment(header_param.as_str());
+    let header_comments = full_header_comment(&interface.name, header_param.as_str());
+
+    // Lets the bridges hand the Kotlin object they wrap back to Kotlin when they are passed as parameter of a callback
+    item_trait.items.push(syn::parse_quote! {
+        #[doc(hidden)]
+        fn jni_callback_object(&self) -> Option<jni::sys::jobject> {
+            None
+        }
+    });
 
     let out = quote! {
         #header_comments
         #item_trait
+        #type_assertions
 
         struct #trait_implementer_name<'a> {
             env: std::rc::Rc<std::cell::RefCell<jni::JNIEnv<'a>>>,
@@ -334,41 +1073,137 @@ pub fn jni_interface(_attr: TokenStream, input: TokenStream) -> TokenStream {
         
         impl<'a> #trait_token for #trait_implementer_name<'a> {
             #(#functions)*
+
+            fn jni_callback_object(&self) -> Option<jni::sys::jobject> {
+                Some(self.callback.as_raw())
+            }
         }
     };
 
+    // The persistent bridge keeps the callback alive with a global reference, so it can be stored and
+    // called from any thread until it's dropped
+    let out = if is_persistent {
+        quote! {
+            #out
+
+            struct #global_trait_implementer_name {
+                vm: jni::JavaVM,
+                callback: jni::objects::GlobalRef,
+            }
+
+            impl #trait_token for #global_trait_implementer_name {
+                #(#global_functions)*
+
+                fn jni_callback_object(&self) -> Option<jni::sys::jobject> {
+                    Some(self.callback.as_obj().as_raw())
+                }
+            }
+
+            impl crate::JniPersistentInterface for #trait_implementer_name<'_> {
+                type GlobalBridge = #global_trait_implementer_name;
+
+                fn global_bridge(env: &jni::JNIEnv, callback: &jni::objects::JObject) -> Self::GlobalBridge {
+                    #global_trait_implementer_name {
+                        vm: env.get_java_vm().expect("Could not get the Java VM"),
+                        callback: env.new_global_ref(callback).expect("Could not create a global reference of the callback"),
+                    }
+                }
+            }
+        }
+    } else {
+        out
+    };
+
     out.into()
 }
 
+/// Export name of a native method of a class in the package given to `jni_init!`. The mangled package
+/// is only known by `jni_init!`, so it's spliced in wherever the symbol references it.
+fn jni_export_name(class_name: &str, method_name: &str, arguments_signature: Option<&str>) -> TokenStream2 {
+    let class_path = format!("{SIGNATURE_PACKAGE_PLACEHOLDER}{class_name}");
+    let symbol = kotars_common::jni_native_method_name(&class_path, method_name, arguments_signature);
+    let mangled_placeholder = kotars_common::jni_mangle(SIGNATURE_PACKAGE_PLACEHOLDER);
+
+    let mut parts = Vec::new();
+    for (index, part) in symbol.split(mangled_placeholder.as_str()).enumerate() {
+        if index > 0 {
+            parts.push(quote! { jni_mangled_package_path!() });
+        }
+        parts.push(quote! { #part });
+    }
+
+    quote! { concat!(#(#parts),*) }
+}
+
+/// The Kotlin methods called through JNI take nullable unsigned types as `Any?`.
+fn kotlin_jni_method_signature_type(jni_type: &JniType) -> String {
+    match jni_type {
+        JniType::Option(ty) if ty.is_unsigned() => "Ljava/lang/Object;".to_string(),
+        jni_type => structs::jni_type_to_jni_method_signature_type(jni_type),
+    }
+}
+
 fn rust_property_to_jni_type(
     ty: &JniType,
     param: &TokenStream2,
     struct_parameter: &TokenStream2,
 ) -> TokenStream2 {
     match ty {
+        JniType::Int8 => {
+            quote! {
+                let #param = #struct_parameter as jni::sys::jbyte;
+            }
+        }
+        JniType::Int16 => {
+            quote! {
+                let #param = #struct_parameter as jni::sys::jshort;
+            }
+        }
         JniType::Int32 => {
             quote! {
                 let #param = #struct_parameter as jni::sys::jint;
             }
         }
+        JniType::ISize | JniType::USize | JniType::Char => {
+            let transformation = transform_rust_to_jni_type(ty, &param.to_string(), false);
+            quote! {
+                let #param = #struct_parameter;
+                #transformation
+            }
+        }
         JniType::Int64 => {
             quote! {
                 let #param = #struct_parameter as jni::sys::jlong;
             }
         }
+        JniType::UInt8 => {
+            quote! {
+                let #param = #struct_parameter as jni::sys::jbyte;
+            }
+        }
+        JniType::UInt16 => {
+            quote! {
+                let #param = #struct_parameter as jni::sys::jshort;
+            }
+        }
+        JniType::UInt32 => {
+            quote! {
+                let #param = #struct_parameter as jni::sys::jint;
+            }
+        }
         JniType::UInt64 => {
             quote! {
-                let #param = #struct_parameter as jni::sys::jlong; // TODO This should be unsigned, perhaps use an object?
+                let #param = #struct_parameter as jni::sys::jlong;
             }
         }
         JniType::Float32 => {
             quote! {
-                let #param = #struct_parameter as jni::sys::jfloat; // TODO This should be unsigned, perhaps use an object?
+                let #param = #struct_parameter as jni::sys::jfloat;
             }
         }
         JniType::Float64 => {
             quote! {
-                let #param = #struct_parameter as jni::sys::jdouble; // TODO This should be unsigned, perhaps use an object?
+                let #param = #struct_parameter as jni::sys::jdouble;
             }
         }
         JniType::String => {            
@@ -395,7 +1230,7 @@ fn rust_property_to_jni_type(
                 let #param: jni::objects::JValue = jni::objects::JValue::Object(&#param);
             }
         }
-        JniType::Receiver(_) | JniType::CustomType(_) => {
+        JniType::Receiver(_) | JniType::CustomType(_) | JniType::Enum(_) => {
             quote! {
                 let #param: jni::objects::JObject = {
                     let mut env = rc_env.borrow_mut();
@@ -404,47 +1239,59 @@ fn rust_property_to_jni_type(
                 let #param: jni::objects::JValue = jni::objects::JValue::Object(&#param);
             }
         }
-        JniType::Interface(_) => todo!(),
-        JniType::Void => todo!(),
-        JniType::Vec(ty) => {
-            if let JniType::String = ty.as_ref() {
-                let individual_item_transformation = transform_rust_to_jni_type(ty, "el", false, true);
-                quote! {
-                    let #param = {
-                        let mut env = rc_env.borrow_mut();
-                        let default = env.new_string("".to_string())
-                            .unwrap();
-                        let arr = env.new_object_array(#param.len() as jni::sys::jsize, "java/lang/String", default).unwrap();
-                        for (i, el) in #param.iter().enumerate() {
-                            #individual_item_transformation
-
-                            env.set_object_array_element(&arr, i as jni::sys::jsize, el).expect("Set object array element failed");
-                        }
+        JniType::Interface(name) => {
+            let error_msg = format!("Only implementations of {name} coming from Kotlin can be passed back to Kotlin");
 
-                        arr
-                    };
-                    let #param: jni::objects::JValue = (&#param).into();
-                }
-            } else {
-                todo!("Only string vectors supported for now")
+            quote! {
+                let #param = #struct_parameter
+                    .jni_callback_object()
+                    .expect(#error_msg);
+                let #param = unsafe { jni::objects::JObject::from_raw(#param) };
+                let #param: jni::objects::JValue = jni::objects::JValue::Object(&#param);
             }
         }
-        JniType::Option(ty) => {
-            todo!()
-            // quote! {
-            //     env.is_same_object(&callback, JObject::null());
-            // }
+        JniType::Void | JniType::Result(_) | JniType::DirectBuffer | JniType::RustBuffer => {
+            unreachable!("Void, Result and buffer values are rejected by the validation of the macro input")
         }
+        JniType::Vec(_) | JniType::HashSet(_) | JniType::BTreeSet(_) | JniType::HashMap(..) | JniType::BTreeMap(..) |
+        JniType::Tuple(_) | JniType::Option(_) | JniType::PrimitiveArray(_) => {
+            let transformation = transform_rust_to_jni_type(ty, &param.to_string(), false);
+            quote! {
+                let #param = #struct_parameter;
+                #transformation
+                let #param: jni::objects::JValue = (&#param).into();
+            }
+        }
+    }
+}
+
+/// Converts the referenced type of borrowed types, along with whether the type was borrowed and whether it was
+/// borrowed mutably. Aliases are resolved first, as they may stand for a reference.
+fn borrowed_jni_type(ty: &syn::Type) -> (JniType, bool, bool) {
+    match type_resolver::resolved_type(ty) {
+        syn::Type::Reference(reference) => (type_resolver::jni_type(&reference.elem), true, reference.mutability.is_some()),
+        ty => (type_resolver::jni_type(&ty), false, false),
     }
 }
 
+fn is_cow(ty: &syn::Type) -> bool {
+    matches!(
+        type_resolver::resolved_type(ty),
+        syn::Type::Path(type_path) if type_path.path.segments.last().is_some_and(|segment| segment.ident == "Cow")
+    )
+}
+
 fn get_return_type_from_method(return_type: &ReturnType) -> Option<JniType> {
     match return_type {
         ReturnType::Default => None,
         ReturnType::Type(_token, typ) => {
-            let rt: JniType = quote::quote!(#typ).to_string().into();
+            let (rt, _, _) = borrowed_jni_type(typ);
 
-            Some(rt)
+            // `-> ()` is the same as not having a return type
+            match rt {
+                JniType::Void => None,
+                rt => Some(rt),
+            }
         }
     }
 }
@@ -455,31 +1302,17 @@ fn get_parameters_from_method(inputs: &Punctuated<FnArg, Comma>) -> Vec<Paramete
         .map(|param| {
             match param {
                 FnArg::Receiver(rec) => {
-                    if rec.reference.is_none() {
-                        // TODO check what would happen if the memory is freed while the JVM still points to it
-                        panic!("You must not take ownership of `self` to prevent crashes in the JVM after freeing the memory")
-                    }
-
                     Parameter::Receiver {
                         is_mutable: rec.mutability.is_some(),
                     }
                 }
                 FnArg::Typed(pat_type) => {
                     let pat = &pat_type.pat;
-                    let ty = &pat_type.ty;
-                    let ty = quote! {#ty}.to_string();
-                    let ty_name = ty
-                        .replace('&', "")
-                        .replace("mut", "")
-                        .trim()
-                        .to_string();
-
-                    let is_borrow = ty.contains('&');
-                    let is_mutable = ty.contains("mut");
+                    let (ty, is_borrow, is_mutable) = borrowed_jni_type(&pat_type.ty);
 
                     Parameter::Typed {
                         name: quote! {#pat}.to_string(),
-                        ty: ty_name.into(),
+                        ty,
                         is_borrow,
                         is_mutable,
                     }
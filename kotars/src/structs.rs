@@ -1,23 +1,22 @@
 extern crate proc_macro;
 
-use std::fmt::format;
-use kotars_common::{Field, JniType, RsStruct};
+use kotars_common::{ClassSync, Field, JniType, RsClass, RsStruct};
 use quote::quote;
 use syn::__private::{str, TokenStream2};
-use syn::{ItemStruct, Visibility};
+use syn::{Fields, ItemStruct, Visibility};
 
-use crate::{full_header_comment, rust_property_to_jni_type};
+use crate::type_resolver;
+use crate::types_transformations::{transform_jni_type_to_rust, transform_jvalue_to_jni_type};
+use crate::{full_header_comment, jni_export_name, rust_property_to_jni_type, SIGNATURE_PACKAGE_PLACEHOLDER};
 
-const PKG_NAME: &str = "<PKG_NAME>";
-
-pub struct Class(RsStruct);
+pub struct Class(RsClass);
 
 pub trait JniGenerator {
     fn generated_methods(&self) -> Vec<TokenStream2>;
 }
 
-impl From<RsStruct> for Class {
-    fn from(value: RsStruct) -> Self {
+impl From<RsClass> for Class {
+    fn from(value: RsClass) -> Self {
         Class(value)
     }
 }
@@ -34,8 +33,9 @@ impl JniGenerator for Class {
     fn generated_methods(&self) -> Vec<TokenStream2> {
         let map_to_class: TokenStream2 = self.map_to_class_func();
         let drop: TokenStream2 = self.drop_func();
+        let cell: TokenStream2 = self.cell_impl();
 
-        vec![map_to_class, drop]
+        vec![map_to_class, drop, cell]
     }
 }
 
@@ -56,7 +56,7 @@ impl Class {
         let struct_json = serde_json::to_string(&self.0).unwrap();
 
         let header_param = format!("JNI_CLASS {struct_json}");
-        let header_comments = full_header_comment(header_param.as_str());
+        let header_comments = full_header_comment(struct_name, header_param.as_str());
 
         quote! {
             #header_comments
@@ -71,14 +71,14 @@ impl Class {
                     };
                     let error_msg_new_object = format!("New object failed {class_path}");
 
-                    let pointer = Box::into_raw(Box::new(self)) as jni::sys::jlong;
-                    let constructor_signature = #constructor_signature.replace("<PKG_NAME>/", package_name_for_signature.as_str());
+                    let cell = <<#struct_token as crate::JniClass>::Cell as crate::JniClassCell>::new(self);
+                    let pointer = Box::into_raw(Box::new(cell)) as jni::sys::jlong;
 
                     let error_msg = format!("Find class failed for {class_path}");
                     let class = env.find_class(class_path).expect(error_msg.as_str());
 
                     let constructor_args: &[jni::objects::JValue] = &[pointer.into()];
-                    let obj = env.new_object(class, constructor_signature.as_str(), constructor_args).expect(&error_msg_new_object);
+                    let obj = env.new_object(class, #constructor_signature, constructor_args).expect(&error_msg_new_object);
                     obj
                 }
             }
@@ -86,19 +86,52 @@ impl Class {
     }
 
     fn drop_func(&self) -> TokenStream2 {
-        let drop_func_header = format!("Java_{}Obj_destroy", self.0.name);
-        let drop_func_header: TokenStream2 = syn::parse_str(&drop_func_header).unwrap();
+        let drop_func_export_name = jni_export_name(&format!("{}Obj", self.0.name), "destroy", None);
+        let drop_func_name: TokenStream2 = syn::parse_str(&format!("jni_{}Obj_destroy", self.0.name)).unwrap();
         let struct_token: TokenStream2 = syn::parse_str(&self.0.name).unwrap();
+        let panic_fn_name = format!("{}::drop", self.0.name);
 
         quote! {
-            #[no_mangle]
-            pub unsafe extern "system" fn #drop_func_header(
-                _env: jni::JNIEnv,
+            #[export_name = #drop_func_export_name]
+            pub unsafe extern "system" fn #drop_func_name(
+                mut env: jni::JNIEnv,
                 _class: jni::objects::JClass,
                 jni_pointer: jni::sys::jlong,
             ) {
-                drop(Box::from_raw(jni_pointer as *mut #struct_token))
+                crate::catch_jni_panic(&mut env, #panic_fn_name, || {
+                    drop(Box::from_raw(jni_pointer as *mut <#struct_token as crate::JniClass>::Cell))
+                })
+            }
+        }
+    }
+
+    fn cell_impl(&self) -> TokenStream2 {
+        let struct_token: TokenStream2 = syn::parse_str(&self.0.name).unwrap();
+
+        let cell = match self.0.sync {
+            ClassSync::None => quote! { crate::UnsynchronizedCell<#struct_token> },
+            ClassSync::Mutex => quote! { std::sync::Mutex<#struct_token> },
+            ClassSync::RwLock => quote! { std::sync::RwLock<#struct_token> },
+        };
+
+        // Locked instances are used by several threads, which requires the lock to be `Sync`
+        let sync_assertion = if self.0.sync == ClassSync::None {
+            quote! {}
+        } else {
+            quote! {
+                const _: fn() = || {
+                    fn assert_sync<T: Sync>() {}
+                    assert_sync::<#cell>();
+                };
+            }
+        };
+
+        quote! {
+            impl crate::JniClass for #struct_token {
+                type Cell = #cell;
             }
+
+            #sync_assertion
         }
     }
 }
@@ -117,14 +150,19 @@ impl DataClass {
             .join("");
 
         let transformations = generate_struct_fields_transformation(&self.0.fields);
+        let fields_from_jobject = generate_struct_fields_from_jobject(struct_name, &self.0.fields);
+        let members = generate_struct_fields_members(&self.0.fields);
+        let bindings = generate_struct_fields_bindings(&self.0.fields);
         let params_into_array = generate_struct_fields_mapping_into_array(&self.0.fields);
         let constructor_signature = format!("({constructor_types_signature})V");
         let struct_json = serde_json::to_string(&self.0).unwrap();
 
         let header_param = format!("JNI_DATA_CLASS {struct_json}");
-        let header_comments = full_header_comment(header_param.as_str());
+        let header_comments = full_header_comment(struct_name, header_param.as_str());
 
         quote! {
+            impl crate::JniValue for #struct_token {}
+
             #header_comments
             impl <'local> crate::IntoEnv<'local, jni::objects::JObject<'local>> for #struct_token {
                 fn into_env(self, env: &mut std::cell::RefMut<'_, jni::JNIEnv<'local>>) -> jni::objects::JObject<'local> {
@@ -137,7 +175,7 @@ impl DataClass {
                         format!("{}/{}", package_name_for_signature, #struct_name)
                     };
 
-                    let constructor_signature = #constructor_signature.replace(#PKG_NAME, package_name_for_signature.as_str());
+                    let constructor_signature = crate::jni_signature(#constructor_signature);
 
                     let class = {
                         let mut env = rc_env.borrow_mut();
@@ -157,6 +195,16 @@ impl DataClass {
                     obj
                 }
             }
+
+            impl <'local> crate::IntoEnv<'local, #struct_token> for jni::objects::JObject<'local> {
+                fn into_env(self, env: &mut std::cell::RefMut<'_, jni::JNIEnv<'local>>) -> #struct_token {
+                    let rc_env = std::rc::Rc::new(std::cell::RefCell::new(env));
+
+                    #(#fields_from_jobject)*
+
+                    #struct_token { #(#members: #bindings,)* }
+                }
+            }
         }
     }
 }
@@ -171,7 +219,10 @@ pub fn generate_struct_fields_transformation(fields: &[Field]) -> Vec<TokenStrea
             let param = syn::parse_str(name).unwrap();
 
             let struct_parameter = match field.name.as_ref() {
-                None => quote! { self.#index },
+                None => {
+                    let index = syn::Index::from(index);
+                    quote! { self.#index }
+                }
                 Some(_) => {
                     quote! { self.#param }
                 }
@@ -182,6 +233,54 @@ pub fn generate_struct_fields_transformation(fields: &[Field]) -> Vec<TokenStrea
         .collect::<Vec<TokenStream2>>()
 }
 
+pub fn generate_struct_fields_from_jobject(owner_name: &str, fields: &[Field]) -> Vec<TokenStream2> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let name = field.safe_name(&index);
+            let param: TokenStream2 = syn::parse_str(&name).unwrap();
+            let kotlin_field_name = kotars_common::string_to_camel_case(&name);
+            let field_signature = jni_type_to_jni_method_signature_type(&field.ty);
+            let error_msg = format!("Could not get field {kotlin_field_name} of {owner_name}");
+
+            let jvalue_transformation = transform_jvalue_to_jni_type(&field.ty, &name);
+            let jni_type_transformation = transform_jni_type_to_rust(&field.ty, &name, false);
+
+            quote! {
+                let #param = {
+                    let mut env = rc_env.borrow_mut();
+                    env.get_field(&self, #kotlin_field_name, crate::jni_signature(#field_signature)).expect(#error_msg)
+                };
+                #jvalue_transformation
+                #jni_type_transformation
+            }
+        })
+        .collect::<Vec<TokenStream2>>()
+}
+
+pub fn generate_struct_fields_members(fields: &[Field]) -> Vec<TokenStream2> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| match &field.name {
+            Some(name) => syn::parse_str(name).unwrap(),
+            None => {
+                let index = syn::Index::from(index);
+                quote! { #index }
+            }
+        })
+        .collect()
+}
+
+pub fn generate_struct_fields_bindings(fields: &[Field]) -> Vec<TokenStream2> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| syn::parse_str(&field.safe_name(&index)).unwrap())
+        .collect()
+}
+
 pub fn generate_method_fields_transformation(fields: &[Field]) -> Vec<TokenStream2> {
     fields
         .iter()
@@ -192,7 +291,7 @@ pub fn generate_method_fields_transformation(fields: &[Field]) -> Vec<TokenStrea
             let param = syn::parse_str(name).unwrap_or_else(|_| panic!("Couldn't parse {name}"));
 
             let struct_parameter = match field.name.as_ref() {
-                None => panic!("All interface function fields should be named."),
+                None => unreachable!("Parameters of interface functions are always named"),
                 Some(_) => {
                     quote! { #param }
                 }
@@ -218,41 +317,151 @@ pub fn generate_struct_fields_mapping_into_array(fields: &[Field]) -> Vec<TokenS
 
 pub fn jni_type_to_jni_method_signature_type(jni_type: &JniType) -> String {
     match jni_type {
+        JniType::Int8 => "B".to_string(),
+        JniType::Int16 => "S".to_string(),
         JniType::Int32 => "I".to_string(),
-        JniType::Int64 | JniType::Receiver(_) => "J".to_string(),
-        JniType::UInt64 => "J".to_string(), // TODO This should be unsigned, perhaps use an object?
+        JniType::Int64 | JniType::ISize | JniType::USize | JniType::Receiver(_) => "J".to_string(),
+        JniType::UInt8 => "B".to_string(),
+        JniType::UInt16 => "S".to_string(),
+        JniType::UInt32 => "I".to_string(),
+        JniType::UInt64 => "J".to_string(),
         JniType::Float32 => "F".to_string(),
         JniType::Float64 => "D".to_string(),
         JniType::String => "Ljava/lang/String;".to_string(),
         JniType::Boolean => "Z".to_string(),
+        JniType::Char => "I".to_string(),
         JniType::ByteArray => "[B".to_string(),
-        JniType::CustomType(name) | JniType::Interface(name) => {
-            // TODO At some point restore supporting package names format!("L{PKG_NAME}/{name};")
-            format!("L{name};")
+        JniType::PrimitiveArray(ty) => format!("[{}", jni_type_to_jni_method_signature_type(ty)),
+        JniType::DirectBuffer => "Ljava/nio/ByteBuffer;".to_string(),
+        JniType::RustBuffer => format!("L{SIGNATURE_PACKAGE_PLACEHOLDER}RustBuffer;"),
+        JniType::CustomType(name) | JniType::Enum(name) | JniType::Interface(name) => {
+            format!("L{SIGNATURE_PACKAGE_PLACEHOLDER}{name};")
         }
         JniType::Void => "V".to_string(),
         JniType::Vec(ty) => {
-            let inner_ty = jni_type_to_jni_method_signature_type(ty);
+            let inner_ty = jni_type_to_boxed_signature_type(ty);
+            format!("[{inner_ty}")
+        },
+        JniType::HashSet(_) | JniType::BTreeSet(_) => "Ljava/util/Set;".to_string(),
+        JniType::HashMap(..) | JniType::BTreeMap(..) => "Ljava/util/Map;".to_string(),
+        JniType::Tuple(element_types) => {
+            let arity = element_types.len();
+            let class_name = kotars_common::tuple_class_name(arity);
+
+            if kotars_common::is_generated_tuple(arity) {
+                format!("L{SIGNATURE_PACKAGE_PLACEHOLDER}{class_name};")
+            } else {
+                format!("Lkotlin/{class_name};")
+            }
+        }
+        JniType::Option(ty) => jni_type_to_boxed_signature_type(ty),
+        JniType::Result(ty) => jni_type_to_jni_method_signature_type(ty),
+    }
+}
+
+pub struct BoxedPrimitive {
+    pub class: &'static str,
+    pub box_method: &'static str,
+    pub unbox_method: &'static str,
+    pub signature: &'static str,
+}
+
+pub fn jni_type_to_boxed_primitive(jni_type: &JniType) -> Option<BoxedPrimitive> {
+    let (class, box_method, unbox_method, signature) = match jni_type {
+        JniType::Int8 => ("java/lang/Byte", "valueOf", "byteValue", "B"),
+        JniType::Int16 => ("java/lang/Short", "valueOf", "shortValue", "S"),
+        JniType::Int32 => ("java/lang/Integer", "valueOf", "intValue", "I"),
+        JniType::Int64 | JniType::ISize | JniType::USize => ("java/lang/Long", "valueOf", "longValue", "J"),
+        JniType::UInt8 => ("kotlin/UByte", "box-impl", "unbox-impl", "B"),
+        JniType::UInt16 => ("kotlin/UShort", "box-impl", "unbox-impl", "S"),
+        JniType::UInt32 => ("kotlin/UInt", "box-impl", "unbox-impl", "I"),
+        JniType::UInt64 => ("kotlin/ULong", "box-impl", "unbox-impl", "J"),
+        JniType::Float32 => ("java/lang/Float", "valueOf", "floatValue", "F"),
+        JniType::Float64 => ("java/lang/Double", "valueOf", "doubleValue", "D"),
+        JniType::Boolean => ("java/lang/Boolean", "valueOf", "booleanValue", "Z"),
+        JniType::Char => ("java/lang/Integer", "valueOf", "intValue", "I"),
+        _ => return None,
+    };
+
+    Some(BoxedPrimitive {
+        class,
+        box_method,
+        unbox_method,
+        signature,
+    })
+}
+
+pub struct PrimitiveArray {
+    pub array_type: TokenStream2,
+    pub element_type: TokenStream2,
+    pub new_array: TokenStream2,
+    pub get_region: TokenStream2,
+    pub set_region: TokenStream2,
+}
+
+pub fn jni_type_to_primitive_array(jni_type: &JniType) -> PrimitiveArray {
+    let (array_type, element_type, name) = match jni_type {
+        JniType::Int8 => (quote! { JByteArray }, quote! { jbyte }, "byte"),
+        JniType::Int16 => (quote! { JShortArray }, quote! { jshort }, "short"),
+        JniType::Int32 => (quote! { JIntArray }, quote! { jint }, "int"),
+        JniType::Int64 => (quote! { JLongArray }, quote! { jlong }, "long"),
+        JniType::Float32 => (quote! { JFloatArray }, quote! { jfloat }, "float"),
+        JniType::Float64 => (quote! { JDoubleArray }, quote! { jdouble }, "double"),
+        JniType::Boolean => (quote! { JBooleanArray }, quote! { jboolean }, "boolean"),
+        ty => unreachable!("{ty:?} doesn't have a primitive array"),
+    };
+    let method = |method: String| syn::parse_str::<TokenStream2>(&method).unwrap();
+
+    PrimitiveArray {
+        array_type: quote! { jni::objects::#array_type },
+        element_type: quote! { jni::sys::#element_type },
+        new_array: method(format!("new_{name}_array")),
+        get_region: method(format!("get_{name}_array_region")),
+        set_region: method(format!("set_{name}_array_region")),
+    }
+}
+
+pub fn jni_type_to_boxed_signature_type(jni_type: &JniType) -> String {
+    match jni_type {
+        JniType::Option(ty) => jni_type_to_boxed_signature_type(ty),
+        JniType::Vec(ty) => {
+            let inner_ty = jni_type_to_boxed_signature_type(ty);
             format!("[{inner_ty}")
+        }
+        _ => match jni_type_to_boxed_primitive(jni_type) {
+            Some(boxed) => format!("L{};", boxed.class),
+            None => jni_type_to_jni_method_signature_type(jni_type),
         },
-        JniType::Option(ty) => jni_type_to_jni_method_signature_type(ty),
+    }
+}
+
+pub fn jni_type_to_array_element_class(jni_type: &JniType) -> String {
+    let signature = jni_type_to_boxed_signature_type(jni_type);
+
+    match signature.strip_prefix('L').and_then(|signature| signature.strip_suffix(';')) {
+        Some(class) => class.to_string(),
+        None => signature,
     }
 }
 
 fn generate_field_mapping_into_array(ty: &JniType, param: &TokenStream2) -> TokenStream2 {
     match ty {
-        JniType::Int32 | JniType::Int64 | JniType::Float32 | JniType::Float64 | JniType::Boolean => {
+        JniType::Int8 | JniType::Int16 | JniType::Int32 | JniType::Int64 | JniType::ISize | JniType::USize |
+        JniType::Float32 | JniType::Float64 | JniType::Boolean | JniType::Char => {
             quote! { #param.into() }
         }
-        JniType::UInt64 => {
-            quote! { #param.into() } // TODO This should be unsigned, perhaps use an object?
+        JniType::UInt8 | JniType::UInt16 | JniType::UInt32 | JniType::UInt64 => {
+            quote! { #param.into() }
         }
-        JniType::Vec(_) | JniType::CustomType(_) | JniType::Interface(_) | JniType::ByteArray | JniType::String => {
+        JniType::Vec(_) | JniType::HashSet(_) | JniType::BTreeSet(_) | JniType::HashMap(..) | JniType::BTreeMap(..) |
+        JniType::Tuple(_) | JniType::CustomType(_) | JniType::Enum(_) | JniType::Interface(_) | JniType::ByteArray | JniType::String |
+        JniType::PrimitiveArray(_) => {
             quote! { #param }
         }
-        JniType::Receiver(_) => panic!("Structs can not have self as type"),
-        JniType::Void => panic!("Structs can not have Void as type"),
-        JniType::Option(ty) => generate_field_mapping_into_array(ty, param),
+        JniType::Receiver(_) | JniType::Void | JniType::Result(_) | JniType::DirectBuffer | JniType::RustBuffer => {
+            unreachable!("Receiver, Void, Result and buffer fields are rejected by the validation of the macro input")
+        }
+        JniType::Option(_) => quote! { #param },
     }
 }
 
@@ -262,26 +471,24 @@ pub trait FromSyn {
 
 impl FromSyn for RsStruct {
     fn from_syn(value: ItemStruct) -> Self {
-        let fields = value
-            .fields
-            .iter()
-            .map(|field| {
-                let name = field.ident.as_ref().map(|id| quote! { #id }.to_string());
-                let original_ty = &field.ty;
-                let ty = quote! { #original_ty }.to_string();
-                let jni_ty: JniType = ty.into();
-
-                Field {
-                    is_public: matches!(field.vis, Visibility::Public { .. }),
-                    name,
-                    ty: jni_ty,
-                }
-            })
-            .collect::<Vec<Field>>();
-
         RsStruct {
             name: value.ident.to_string(),
-            fields,
+            fields: fields_from_syn(&value.fields),
         }
     }
 }
+
+pub fn fields_from_syn(fields: &Fields) -> Vec<Field> {
+    fields
+        .iter()
+        .map(|field| {
+            let name = field.ident.as_ref().map(|id| quote! { #id }.to_string());
+
+            Field {
+                is_public: matches!(field.vis, Visibility::Public { .. }),
+                name,
+                ty: type_resolver::jni_type(&field.ty),
+            }
+        })
+        .collect::<Vec<Field>>()
+}
@@ -0,0 +1,370 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use kotars_common::JniType;
+use proc_macro2::{Span, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::spanned::Spanned;
+use syn::{GenericArgument, PathArguments, Type};
+
+/// Paths of the standard library types, which are resolved to their name
+const STD_TYPE_PATHS: [(&str, &[&str]); 9] = [
+    ("String", &["std::string", "alloc::string"]),
+    ("Box", &["std::boxed", "alloc::boxed"]),
+    ("Cow", &["std::borrow", "alloc::borrow"]),
+    ("Vec", &["std::vec", "alloc::vec"]),
+    ("Option", &["std::option", "core::option"]),
+    ("HashMap", &["std::collections", "collections", "std::collections::hash_map", "collections::hash_map"]),
+    ("HashSet", &["std::collections", "collections", "std::collections::hash_set", "collections::hash_set"]),
+    ("BTreeMap", &["std::collections", "collections", "alloc::collections", "std::collections::btree_map", "alloc::collections::btree_map"]),
+    ("BTreeSet", &["std::collections", "collections", "alloc::collections", "std::collections::btree_set", "alloc::collections::btree_set"]),
+];
+
+const PRIMITIVE_TYPE_PATHS: [&str; 2] = ["std::primitive", "core::primitive"];
+
+const PRIMITIVE_TYPES: [&str; 15] = [
+    "i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64", "isize", "usize", "f32", "f64", "bool", "char", "str",
+];
+
+/// Aliases are expanded recursively, this limit stops aliases that refer to themselves
+const MAX_ALIAS_DEPTH: usize = 16;
+
+/// Aliases registered by the crate in the `[package.metadata.kotars.aliases]` table of its manifest, from the
+/// path of the alias to the type it stands for. Types are kept as source code, as the tokens of the compiler
+/// can't outlive the macro that created them.
+type Aliases = HashMap<String, String>;
+
+struct LoadedAliases {
+    manifest: PathBuf,
+    modified: Option<SystemTime>,
+    aliases: Rc<Aliases>,
+    errors: Rc<Vec<String>>,
+}
+
+thread_local! {
+    // Every macro resolves many types, so the manifest is only read again when it changes
+    static ALIASES: RefCell<Option<LoadedAliases>> = const { RefCell::new(None) };
+}
+
+pub fn validate_aliases() -> syn::Result<()> {
+    let (_, errors) = load_aliases();
+
+    errors
+        .iter()
+        .map(|err| syn::Error::new(Span::call_site(), err))
+        .reduce(|mut combined, err| {
+            combined.combine(err);
+            combined
+        })
+        .map_or(Ok(()), Err)
+}
+
+fn aliases() -> Rc<Aliases> {
+    // Invalid aliases are left out and reported by `jni_init!`
+    load_aliases().0
+}
+
+fn load_aliases() -> (Rc<Aliases>, Rc<Vec<String>>) {
+    let Some(manifest_dir) = std::env::var_os("CARGO_MANIFEST_DIR") else {
+        return Default::default();
+    };
+    let manifest = PathBuf::from(manifest_dir).join("Cargo.toml");
+    let modified = std::fs::metadata(&manifest).and_then(|metadata| metadata.modified()).ok();
+
+    ALIASES.with(|loaded| {
+        let mut loaded = loaded.borrow_mut();
+
+        match loaded.as_ref() {
+            Some(loaded) if loaded.manifest == manifest && loaded.modified == modified => {
+                (loaded.aliases.clone(), loaded.errors.clone())
+            }
+            _ => {
+                let (aliases, errors) = read_aliases(&manifest);
+                let (aliases, errors) = (Rc::new(aliases), Rc::new(errors));
+                *loaded = Some(LoadedAliases {
+                    manifest,
+                    modified,
+                    aliases: aliases.clone(),
+                    errors: errors.clone(),
+                });
+
+                (aliases, errors)
+            }
+        }
+    })
+}
+
+/// Reads the aliases declared in the manifest, along with the errors of the ones that are invalid.
+fn read_aliases(manifest: &PathBuf) -> (Aliases, Vec<String>) {
+    let Ok(content) = std::fs::read_to_string(manifest) else {
+        return Default::default();
+    };
+    let manifest: toml::Table = match content.parse() {
+        Ok(manifest) => manifest,
+        Err(err) => return (Aliases::new(), vec![format!("unable to read the kotars aliases of {manifest:?}. {err}")]),
+    };
+
+    let Some(aliases) = manifest
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("kotars"))
+        .and_then(|kotars| kotars.get("aliases"))
+    else {
+        return Default::default();
+    };
+
+    let Some(aliases) = aliases.as_table() else {
+        return (Aliases::new(), vec!["[package.metadata.kotars.aliases] must be a table of type aliases".to_string()]);
+    };
+
+    let mut valid_aliases = Aliases::new();
+    let mut errors = Vec::new();
+
+    for (alias, ty) in aliases {
+        let alias_type = ty
+            .as_str()
+            .ok_or_else(|| format!("alias `{alias}` must be a string with the type it stands for"))
+            .and_then(|ty| {
+                syn::parse_str::<Type>(ty).map_err(|err| format!("type `{ty}` of alias `{alias}` is invalid. {err}"))?;
+                let path = syn::parse_str::<syn::Path>(alias).map_err(|err| format!("alias `{alias}` is not a valid path. {err}"))?;
+
+                Ok((path_name(&path), ty.to_string()))
+            });
+
+        match alias_type {
+            Ok((alias, ty)) => {
+                valid_aliases.insert(alias, ty);
+            }
+            Err(err) => errors.push(err),
+        }
+    }
+
+    (valid_aliases, errors)
+}
+
+/// Expands the aliases of the crate and replaces the paths of the standard library types by their name.
+/// The resolved type keeps the spans of the original one, so errors point at the type as written.
+pub fn resolve_type(ty: &Type) -> syn::Result<Type> {
+    resolve_type_with_aliases(ty, &aliases(), 0)
+}
+
+fn resolve_type_with_aliases(ty: &Type, aliases: &Aliases, depth: usize) -> syn::Result<Type> {
+    let resolved = match ty {
+        Type::Paren(paren) => resolve_type_with_aliases(&paren.elem, aliases, depth)?,
+        Type::Group(group) => resolve_type_with_aliases(&group.elem, aliases, depth)?,
+        Type::Reference(reference) => {
+            let mut reference = reference.clone();
+            reference.elem = Box::new(resolve_type_with_aliases(&reference.elem, aliases, depth)?);
+            Type::Reference(reference)
+        }
+        Type::Slice(slice) => {
+            let mut slice = slice.clone();
+            slice.elem = Box::new(resolve_type_with_aliases(&slice.elem, aliases, depth)?);
+            Type::Slice(slice)
+        }
+        Type::Tuple(tuple) => {
+            let mut tuple = tuple.clone();
+            for element in tuple.elems.iter_mut() {
+                *element = resolve_type_with_aliases(element, aliases, depth)?;
+            }
+            Type::Tuple(tuple)
+        }
+        Type::Path(type_path) if type_path.qself.is_none() => {
+            if let Some(alias_ty) = aliases.get(&path_name(&type_path.path)) {
+                if depth == MAX_ALIAS_DEPTH {
+                    return Err(syn::Error::new_spanned(ty, "the aliases of this type refer to themselves"));
+                }
+
+                let alias_ty = syn::parse_str::<Type>(alias_ty)?;
+                let alias_ty = with_arguments(&alias_ty, &type_path.path);
+                let alias_ty = respan(&alias_ty, ty.span())?;
+
+                return resolve_type_with_aliases(&alias_ty, aliases, depth + 1);
+            }
+
+            let mut type_path = type_path.clone();
+            if is_std_type_path(&type_path.path) {
+                let last_segment = type_path.path.segments.pop().map(|segment| segment.into_value());
+                type_path.path.leading_colon = None;
+                type_path.path.segments.clear();
+                type_path.path.segments.extend(last_segment);
+            }
+
+            if let Some(segment) = type_path.path.segments.last_mut() {
+                if let PathArguments::AngleBracketed(arguments) = &mut segment.arguments {
+                    for argument in arguments.args.iter_mut() {
+                        if let GenericArgument::Type(argument_ty) = argument {
+                            *argument_ty = resolve_type_with_aliases(argument_ty, aliases, depth)?;
+                        }
+                    }
+                }
+            }
+
+            Type::Path(type_path)
+        }
+        ty => ty.clone(),
+    };
+
+    Ok(resolved)
+}
+
+/// Any `Result`, like `std::io::Result`, is converted by its `Ok` type, so its path isn't relevant
+fn is_std_type_path(path: &syn::Path) -> bool {
+    let Some(name) = path.segments.last().map(|segment| segment.ident.to_string()) else {
+        return false;
+    };
+    let prefix = path.segments
+        .iter()
+        .take(path.segments.len() - 1)
+        .map(|segment| segment.ident.to_string())
+        .collect::<Vec<String>>()
+        .join("::");
+
+    if name == "Result" {
+        return true;
+    }
+
+    if PRIMITIVE_TYPES.contains(&name.as_str()) {
+        return PRIMITIVE_TYPE_PATHS.contains(&prefix.as_str());
+    }
+
+    STD_TYPE_PATHS
+        .iter()
+        .any(|(std_name, prefixes)| *std_name == name && prefixes.contains(&prefix.as_str()))
+}
+
+/// Generic arguments given to an alias without them are passed to the type it stands for
+fn with_arguments(alias_ty: &Type, path: &syn::Path) -> Type {
+    let arguments = path.segments.last().map(|segment| &segment.arguments);
+    let mut alias_ty = alias_ty.clone();
+
+    if let (Type::Path(alias_path), Some(arguments)) = (&mut alias_ty, arguments) {
+        if let Some(segment) = alias_path.path.segments.last_mut() {
+            if segment.arguments.is_none() {
+                segment.arguments = arguments.clone();
+            }
+        }
+    }
+
+    alias_ty
+}
+
+fn respan(ty: &Type, span: Span) -> syn::Result<Type> {
+    fn respan_tokens(tokens: TokenStream, span: Span) -> TokenStream {
+        tokens
+            .into_iter()
+            .map(|mut token| {
+                if let TokenTree::Group(group) = &token {
+                    let mut new_group = proc_macro2::Group::new(group.delimiter(), respan_tokens(group.stream(), span));
+                    new_group.set_span(span);
+                    token = TokenTree::Group(new_group);
+                } else {
+                    token.set_span(span);
+                }
+
+                token
+            })
+            .collect()
+    }
+
+    syn::parse2(respan_tokens(ty.to_token_stream(), span))
+}
+
+fn path_name(path: &syn::Path) -> String {
+    path.segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect::<Vec<String>>()
+        .join("::")
+}
+
+/// Resolves a type that has been validated by the macro.
+pub fn resolved_type(ty: &Type) -> Type {
+    resolve_type(ty).unwrap_or_else(|err| unreachable!("Types are resolved when they are validated. {err}"))
+}
+
+/// Converts a type that has been validated by the macro.
+pub fn jni_type(ty: &Type) -> JniType {
+    resolved_jni_type(&resolved_type(ty))
+}
+
+fn resolved_jni_type(ty: &Type) -> JniType {
+    match ty {
+        Type::Reference(reference) => resolved_jni_type(&reference.elem),
+        // Borrowed slices are copied from and into arrays of bytes or primitives, the only slices allowed by the validation
+        Type::Slice(slice) => match resolved_jni_type(&slice.elem) {
+            JniType::UInt8 => JniType::ByteArray,
+            ty => JniType::PrimitiveArray(Box::new(ty)),
+        },
+        Type::Tuple(tuple) if tuple.elems.is_empty() => JniType::Void,
+        Type::Tuple(tuple) => JniType::Tuple(tuple.elems.iter().map(resolved_jni_type).collect()),
+        Type::ImplTrait(impl_trait) => {
+            let interface_name = impl_trait.bounds
+                .iter()
+                .find_map(|bound| match bound {
+                    syn::TypeParamBound::Trait(bound) => bound.path.segments.last(),
+                    _ => None,
+                })
+                .map(|segment| segment.ident.to_string())
+                .unwrap_or_else(|| unreachable!("Interfaces are validated to have a trait bound"));
+
+            JniType::Interface(interface_name)
+        }
+        Type::Path(type_path) => {
+            let segment = type_path.path.segments
+                .last()
+                .unwrap_or_else(|| unreachable!("Paths have at least one segment"));
+            let arguments = match &segment.arguments {
+                PathArguments::AngleBracketed(arguments) => arguments.args
+                    .iter()
+                    .filter_map(|argument| match argument {
+                        GenericArgument::Type(ty) => Some(ty),
+                        _ => None,
+                    })
+                    .collect::<Vec<&Type>>(),
+                _ => Vec::new(),
+            };
+            let argument = |index: usize| Box::new(resolved_jni_type(arguments[index]));
+            let name = segment.ident.to_string();
+
+            match (name.as_str(), arguments.len()) {
+                ("i8", 0) => JniType::Int8,
+                ("i16", 0) => JniType::Int16,
+                ("i32", 0) => JniType::Int32,
+                ("i64", 0) => JniType::Int64,
+                ("u8", 0) => JniType::UInt8,
+                ("u16", 0) => JniType::UInt16,
+                ("u32", 0) => JniType::UInt32,
+                ("u64", 0) => JniType::UInt64,
+                ("isize", 0) => JniType::ISize,
+                ("usize", 0) => JniType::USize,
+                ("f32", 0) => JniType::Float32,
+                ("f64", 0) => JniType::Float64,
+                ("String", 0) | ("str", 0) => JniType::String,
+                // `Cow<str>` parameters are built from the owned string
+                ("Cow", 1) => *argument(0),
+                ("DirectBuffer", 0) => JniType::DirectBuffer,
+                ("Box", 1) if matches!(*argument(0), JniType::DirectBuffer) => JniType::RustBuffer,
+                ("bool", 0) => JniType::Boolean,
+                ("char", 0) => JniType::Char,
+                ("Vec", 1) => match argument(0) {
+                    ty if matches!(*ty, JniType::UInt8) => JniType::ByteArray,
+                    ty if ty.has_primitive_array() => JniType::PrimitiveArray(ty),
+                    ty => JniType::Vec(ty),
+                },
+                ("Option", 1) => JniType::Option(argument(0)),
+                ("HashSet", 1) => JniType::HashSet(argument(0)),
+                ("BTreeSet", 1) => JniType::BTreeSet(argument(0)),
+                ("HashMap", 2) => JniType::HashMap(argument(0), argument(1)),
+                ("BTreeMap", 2) => JniType::BTreeMap(argument(0), argument(1)),
+                // Only the `Ok` type is converted, errors are thrown as exceptions with their message
+                ("Result", arity) if arity > 0 => JniType::Result(argument(0)),
+                _ => JniType::CustomType(name),
+            }
+        }
+        ty => unreachable!("Type `{}` is rejected by the validation of the macro input", ty.to_token_stream()),
+    }
+}
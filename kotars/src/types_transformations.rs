@@ -1,19 +1,26 @@
-use quote::quote;
+use proc_macro2::Span;
+use quote::{quote, quote_spanned};
 use syn::__private::TokenStream2;
 use kotars_common::JniType;
 
-pub fn transform_jni_type_to_rust(
-    jni_type: &JniType,
-    param_name: &str,
-    is_optional: bool,
-) -> TokenStream2 {
+use crate::structs::{jni_type_to_array_element_class, jni_type_to_boxed_primitive, jni_type_to_primitive_array};
+
+pub fn transform_jni_type_to_rust(jni_type: &JniType, param_name: &str, is_borrow: bool) -> TokenStream2 {
     match jni_type {
-        JniType::Int32 => transform_jint_to_i32(param_name, is_optional),
+        JniType::Int8 => transform_types(param_name, quote! { i8 }),
+        JniType::Int16 => transform_types(param_name, quote! { i16 }),
+        JniType::Int32 => transform_jint_to_i32(param_name),
         JniType::Int64 => transform_jlong_to_i64(param_name),
-        JniType::UInt64 => transform_jlong_to_u64(param_name), // TODO This should be unsigned, perhaps use an object?
+        JniType::ISize => transform_checked_types(param_name, quote! { isize }),
+        JniType::USize => transform_checked_types(param_name, quote! { usize }),
+        JniType::Char => transform_jint_to_char(param_name),
+        JniType::UInt8 => transform_types(param_name, quote! { u8 }),
+        JniType::UInt16 => transform_types(param_name, quote! { u16 }),
+        JniType::UInt32 => transform_types(param_name, quote! { u32 }),
+        JniType::UInt64 => transform_types(param_name, quote! { u64 }),
         JniType::Float32 => transform_jfloat_to_f32(param_name),
         JniType::Float64 => transform_jdouble_to_f64(param_name),
-        JniType::String => transform_jstring_to_string(param_name, is_optional),
+        JniType::String => transform_jstring_to_string(param_name),
         JniType::Boolean => transform_jbool_to_bool(param_name),
         JniType::ByteArray => {
             let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
@@ -24,31 +31,27 @@ pub fn transform_jni_type_to_rust(
                 };
             }
         }
-        JniType::CustomType(ty) => transform_jobject_to_custom(param_name, ty),
-        JniType::Receiver(ty) => transform_jlong_to_receiver(param_name, ty),
-        JniType::Void => panic!("Void can't be transformed to a Rust type"),
-        JniType::Vec(ty) => todo!("ABDE_Vec2"), //transform_jarray_to_vec(param_name, ty),
-        JniType::Option(ty) => {
-            let transform = transform_jni_type_to_rust(ty, param_name, true);
-            let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
-
-            let q = quote! {
-                let is_null = {
-                    let mut env = rc_env.borrow_mut();
-                    env.is_same_object(&#param, jni::objects::JObject::null()).expect("Could not check if object is null")
-                };
-                
-                let #param = if is_null {
-                    Option::None
-                } else {
-                    #transform
-
-                    // Test
-                    Some(#param)
-                };
-            };
-
-            q
+        JniType::CustomType(ty) | JniType::Enum(ty) => transform_jobject_to_value(param_name, ty),
+        JniType::Receiver(_) | JniType::DirectBuffer => {
+            unreachable!("Receivers and direct buffers are transformed by the function bindings, which know their mutability")
+        }
+        JniType::PrimitiveArray(ty) => transform_jarray_to_primitive_vec(param_name, ty),
+        JniType::Void | JniType::Result(_) | JniType::RustBuffer => {
+            unreachable!("Void, Result and Rust buffer values are rejected by the validation of the macro input")
+        }
+        JniType::Vec(ty) => transform_jarray_to_vec(param_name, ty, is_borrow),
+        JniType::HashSet(ty) => transform_jset_to_set(param_name, quote! { std::collections::HashSet }, ty, is_borrow),
+        JniType::BTreeSet(ty) => transform_jset_to_set(param_name, quote! { std::collections::BTreeSet }, ty, is_borrow),
+        JniType::Tuple(element_types) => transform_jobject_to_tuple(param_name, element_types, is_borrow),
+        JniType::HashMap(key_ty, value_ty) => {
+            transform_jmap_to_map(param_name, quote! { std::collections::HashMap }, key_ty, value_ty, is_borrow)
+        }
+        JniType::BTreeMap(key_ty, value_ty) => {
+            transform_jmap_to_map(param_name, quote! { std::collections::BTreeMap }, key_ty, value_ty, is_borrow)
+        }
+        JniType::Option(_) => transform_jobject_to_rust(jni_type, param_name, is_borrow),
+        JniType::Interface(_) if !is_borrow => {
+            unreachable!("Interfaces taken by value are transformed by the function bindings into their global bridge")
         }
         JniType::Interface(name) => {
             let struct_name = format!("{name}JniBridge");
@@ -69,143 +72,696 @@ pub fn transform_jni_type_to_rust(
     }
 }
 
-// fn transform_jarray_to_vec(param_name: &str, inner_ty: &Box<JniType>) -> TokenStream2 {
-//     let inner_param_name = "iter_item";
-//     let item_transformation = transform_jni_type_to_rust(inner_ty, inner_param_name, false);
-//     
-//     
-// }
+fn transform_jarray_to_vec(param_name: &str, inner_ty: &JniType, is_borrow: bool) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let item_name = format!("{param_name}_item");
+    let item = syn::parse_str::<TokenStream2>(&item_name).unwrap();
+    let item_transformation = transform_jobject_to_rust(inner_ty, &item_name, is_borrow);
 
-fn transform_jlong_to_receiver(param_name: &str, ty: &str) -> TokenStream2 {
-    let param: TokenStream2 = syn::parse_str(param_name).unwrap();
-    let ty: TokenStream2 = syn::parse_str(ty).unwrap();
+    quote! {
+        let #param = {
+            let length = {
+                let env = rc_env.borrow();
+                env.get_array_length(&#param).expect("Could not get the array length")
+            };
+
+            let mut items = Vec::with_capacity(length as usize);
+
+            for index in 0..length {
+                let #item = {
+                    let mut env = rc_env.borrow_mut();
+                    env.get_object_array_element(&#param, index).expect("Could not get the array element")
+                };
+
+                #item_transformation
+
+                items.push(#item);
+            }
+
+            items
+        };
+    }
+}
+
+/// Primitive arrays are copied with a single region copy, instead of reading their elements one by one
+fn transform_jarray_to_primitive_vec(param_name: &str, inner_ty: &JniType) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let values = syn::parse_str::<TokenStream2>(&format!("{param_name}_values")).unwrap();
+    let array = jni_type_to_primitive_array(inner_ty);
+    let element_type = &array.element_type;
+    let get_region = &array.get_region;
+
+    let result = if matches!(inner_ty, JniType::Boolean) {
+        quote! { #values.into_iter().map(|value| value != jni::sys::JNI_FALSE).collect::<Vec<bool>>() }
+    } else {
+        quote! { #values }
+    };
 
     quote! {
-        let mut #param = unsafe { &mut *(#param as *mut #ty) };
+        let #param = {
+            let env = rc_env.borrow();
+            let length = env.get_array_length(&#param).expect("Could not get the array length");
+
+            let mut #values: Vec<#element_type> = vec![Default::default(); length as usize];
+            env.#get_region(&#param, 0, &mut #values).expect("Could not copy the array elements");
+
+            #result
+        };
+    }
+}
+
+/// Slice of JNI values of a `Vec` or slice of primitives, ready to be copied into a primitive array
+pub fn primitive_array_values(param_name: &str, inner_ty: &JniType) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+
+    if matches!(inner_ty, JniType::Boolean) {
+        quote! { &#param.iter().map(|value| *value as jni::sys::jboolean).collect::<Vec<jni::sys::jboolean>>() }
+    } else {
+        quote! { &#param }
     }
 }
 
-pub fn transform_rust_to_jni_type(
-    jni_type: &JniType,
+fn transform_primitive_vec_to_jarray(param_name: &str, inner_ty: &JniType) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let array = jni_type_to_primitive_array(inner_ty);
+    let array_type = &array.array_type;
+    let new_array = &array.new_array;
+    let set_region = &array.set_region;
+    let values = primitive_array_values(param_name, inner_ty);
+
+    quote! {
+        let #param: #array_type = {
+            let mut env = rc_env.borrow_mut();
+            let array = env.#new_array(#param.len() as jni::sys::jsize).expect("Could not create the array");
+            env.#set_region(&array, 0, #values).expect("Could not copy the array elements");
+
+            array
+        };
+    }
+}
+
+fn transform_vec_to_jarray(param_name: &str, inner_ty: &JniType) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let item_name = format!("{param_name}_item");
+    let item = syn::parse_str::<TokenStream2>(&item_name).unwrap();
+    let item_transformation = transform_rust_to_jobject(inner_ty, &item_name);
+    let element_class = jni_type_to_array_element_class(inner_ty);
+
+    quote! {
+        let #param: jni::objects::JObjectArray = {
+            let array = {
+                let mut env = rc_env.borrow_mut();
+                let element_class = crate::jni_signature(#element_class);
+                let error_msg = format!("Could not create an array of {element_class}");
+                env.new_object_array(#param.len() as jni::sys::jsize, element_class, jni::objects::JObject::null())
+                    .expect(&error_msg)
+            };
+
+            for (index, #item) in #param.into_iter().enumerate() {
+                #item_transformation
+
+                let env = rc_env.borrow();
+                env.set_object_array_element(&array, index as jni::sys::jsize, &#item).expect("Set object array element failed");
+                env.delete_local_ref(#item).expect("Could not delete the local reference of the array element");
+            }
+
+            array
+        };
+    }
+}
+
+fn tuple_element_names(param_name: &str, element_types: &[JniType]) -> Vec<String> {
+    (0..element_types.len())
+        .map(|index| format!("{param_name}_{index}"))
+        .collect()
+}
+
+fn tuple_class_path(arity: usize) -> TokenStream2 {
+    let class_name = kotars_common::tuple_class_name(arity);
+
+    if kotars_common::is_generated_tuple(arity) {
+        quote! {
+            {
+                let package_name_for_signature = crate::JNI_PACKAGE_NAME.replace(".", "/");
+
+                if package_name_for_signature.is_empty() {
+                    #class_name.to_string()
+                } else {
+                    format!("{}/{}", package_name_for_signature, #class_name)
+                }
+            }
+        }
+    } else {
+        let class_path = format!("kotlin/{class_name}");
+        quote! { #class_path.to_string() }
+    }
+}
+
+fn transform_jobject_to_tuple(param_name: &str, element_types: &[JniType], is_borrow: bool) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let element_names = tuple_element_names(param_name, element_types);
+
+    let elements_transformations = element_types
+        .iter()
+        .zip(element_names.iter())
+        .enumerate()
+        .map(|(index, (ty, element_name))| {
+            let element = syn::parse_str::<TokenStream2>(element_name).unwrap();
+            let component_method = format!("component{}", index + 1);
+            let error_msg = format!("Could not get {component_method} of {param_name}");
+            let transformation = transform_jobject_to_rust(ty, element_name, is_borrow);
+
+            quote! {
+                let #element = {
+                    let mut env = rc_env.borrow_mut();
+                    env.call_method(&#param, #component_method, "()Ljava/lang/Object;", &[])
+                        .and_then(|value| value.l())
+                        .expect(#error_msg)
+                };
+                #transformation
+            }
+        })
+        .collect::<Vec<TokenStream2>>();
+
+    let elements = element_names
+        .iter()
+        .map(|element_name| syn::parse_str::<TokenStream2>(element_name).unwrap())
+        .collect::<Vec<TokenStream2>>();
+
+    quote! {
+        let #param = {
+            #(#elements_transformations)*
+
+            (#(#elements,)*)
+        };
+    }
+}
+
+fn transform_tuple_to_jobject(param_name: &str, element_types: &[JniType]) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let element_names = tuple_element_names(param_name, element_types);
+    let elements = element_names
+        .iter()
+        .map(|element_name| syn::parse_str::<TokenStream2>(element_name).unwrap())
+        .collect::<Vec<TokenStream2>>();
+
+    let elements_transformations = element_types
+        .iter()
+        .zip(element_names.iter())
+        .map(|(ty, element_name)| transform_rust_to_jobject(ty, element_name))
+        .collect::<Vec<TokenStream2>>();
+
+    let class_path = tuple_class_path(element_types.len());
+    let constructor_signature = format!("({})V", "Ljava/lang/Object;".repeat(element_types.len()));
+
+    quote! {
+        let #param: jni::objects::JObject = {
+            let (#(#elements,)*) = #param;
+
+            #(#elements_transformations)*
+
+            let class_path = #class_path;
+            let mut env = rc_env.borrow_mut();
+            let error_msg = format!("New object failed {class_path}");
+            let tuple = env.new_object(&class_path, #constructor_signature, &[#((&#elements).into(),)*])
+                .expect(&error_msg);
+
+            #(env.delete_local_ref(#elements).expect("Could not delete the local reference of the tuple element");)*
+
+            tuple
+        };
+    }
+}
+
+fn transform_jset_to_set(param_name: &str, set_ty: TokenStream2, inner_ty: &JniType, is_borrow: bool) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let item_name = format!("{param_name}_item");
+    let item = syn::parse_str::<TokenStream2>(&item_name).unwrap();
+    let item_transformation = transform_jobject_to_rust(inner_ty, &item_name, is_borrow);
+
+    quote! {
+        let #param = {
+            let iterator = {
+                let mut env = rc_env.borrow_mut();
+                env.call_method(&#param, "iterator", "()Ljava/util/Iterator;", &[])
+                    .and_then(|value| value.l())
+                    .expect("Could not get the set iterator")
+            };
+
+            let mut items = #set_ty::new();
+
+            loop {
+                let has_next = {
+                    let mut env = rc_env.borrow_mut();
+                    env.call_method(&iterator, "hasNext", "()Z", &[])
+                        .and_then(|value| value.z())
+                        .expect("Could not check if the set has more elements")
+                };
+
+                if !has_next {
+                    break;
+                }
+
+                let #item = {
+                    let mut env = rc_env.borrow_mut();
+                    env.call_method(&iterator, "next", "()Ljava/lang/Object;", &[])
+                        .and_then(|value| value.l())
+                        .expect("Could not get the next set element")
+                };
+
+                #item_transformation
+
+                items.insert(#item);
+            }
+
+            items
+        };
+    }
+}
+
+fn transform_set_to_jset(param_name: &str, set_class: &str, inner_ty: &JniType) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let item_name = format!("{param_name}_item");
+    let item = syn::parse_str::<TokenStream2>(&item_name).unwrap();
+    let item_transformation = transform_rust_to_jobject(inner_ty, &item_name);
+    let error_msg = format!("Could not create an instance of {set_class}");
+
+    quote! {
+        let #param: jni::objects::JObject = {
+            let set = {
+                let mut env = rc_env.borrow_mut();
+                env.new_object(#set_class, "()V", &[]).expect(#error_msg)
+            };
+
+            for #item in #param.into_iter() {
+                #item_transformation
+
+                let mut env = rc_env.borrow_mut();
+                env.call_method(&set, "add", "(Ljava/lang/Object;)Z", &[(&#item).into()])
+                    .expect("Add set element failed");
+                env.delete_local_ref(#item).expect("Could not delete the local reference of the set element");
+            }
+
+            set
+        };
+    }
+}
+
+fn transform_jmap_to_map(
     param_name: &str,
-    is_optional: bool,
-    is_nested_transformation: bool,
+    map_ty: TokenStream2,
+    key_ty: &JniType,
+    value_ty: &JniType,
+    is_borrow: bool,
 ) -> TokenStream2 {
-    match jni_type {
-        JniType::Int32 => transform_i32_to_jint(param_name, is_optional),
-        JniType::Int64 => transform_i64_to_jlong(param_name),
-        JniType::UInt64 => transform_u64_to_jlong(param_name), // TODO This should be unsigned, perhaps use an object?
-        JniType::Float32 => transform_f32_to_jfloat(param_name),
-        JniType::Float64 => transform_f64_to_jdouble(param_name),
-        JniType::String => transform_string_to_jstring(param_name, is_nested_transformation),
-        JniType::Boolean => transform_bool_to_jbool(param_name),
-        JniType::ByteArray => {
-            let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let key_name = format!("{param_name}_key");
+    let key = syn::parse_str::<TokenStream2>(&key_name).unwrap();
+    let value_name = format!("{param_name}_value");
+    let value = syn::parse_str::<TokenStream2>(&value_name).unwrap();
+    let key_transformation = transform_jobject_to_rust(key_ty, &key_name, is_borrow);
+    let value_transformation = transform_jobject_to_rust(value_ty, &value_name, is_borrow);
 
-            quote! {
-                let #param: jni::objects::JByteArray = {
+    quote! {
+        let #param = {
+            let map = {
+                let mut env = rc_env.borrow_mut();
+                jni::objects::JMap::from_env(&mut env, &#param).expect("Could not get the map")
+            };
+
+            let mut entries = {
+                let mut env = rc_env.borrow_mut();
+                map.iter(&mut env).expect("Could not iterate over the map")
+            };
+
+            let mut items = #map_ty::new();
+
+            loop {
+                let entry = {
                     let mut env = rc_env.borrow_mut();
-                    #param.into_env(&mut env)
+                    entries.next(&mut env).expect("Could not get the next map entry")
+                };
+
+                let Some((#key, #value)) = entry else {
+                    break;
                 };
+
+                #key_transformation
+                #value_transformation
+
+                items.insert(#key, #value);
             }
-        }
-        JniType::Vec(ty) => {
-            let individual_item_transformation = transform_rust_to_jni_type(ty, "el", false, true);
-            let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
-            todo!("Idd2");
+
+            items
+        };
+    }
+}
+
+fn transform_map_to_jmap(param_name: &str, map_class: &str, key_ty: &JniType, value_ty: &JniType) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let key_name = format!("{param_name}_key");
+    let key = syn::parse_str::<TokenStream2>(&key_name).unwrap();
+    let value_name = format!("{param_name}_value");
+    let value = syn::parse_str::<TokenStream2>(&value_name).unwrap();
+    let key_transformation = transform_rust_to_jobject(key_ty, &key_name);
+    let value_transformation = transform_rust_to_jobject(value_ty, &value_name);
+    let error_msg = format!("Could not create an instance of {map_class}");
+
+    quote! {
+        let #param: jni::objects::JObject = {
+            let map = {
+                let mut env = rc_env.borrow_mut();
+                env.new_object(#map_class, "()V", &[]).expect(#error_msg)
+            };
+
+            for (#key, #value) in #param.into_iter() {
+                #key_transformation
+                #value_transformation
+
+                let mut env = rc_env.borrow_mut();
+                let previous_value = env
+                    .call_method(
+                        &map,
+                        "put",
+                        "(Ljava/lang/Object;Ljava/lang/Object;)Ljava/lang/Object;",
+                        &[(&#key).into(), (&#value).into()],
+                    )
+                    .and_then(|value| value.l())
+                    .expect("Put map entry failed");
+
+                env.delete_local_ref(previous_value).expect("Could not delete the local reference of the previous map value");
+                env.delete_local_ref(#key).expect("Could not delete the local reference of the map key");
+                env.delete_local_ref(#value).expect("Could not delete the local reference of the map value");
+            }
+
+            map
+        };
+    }
+}
+
+pub fn transform_rust_to_jobject(jni_type: &JniType, param_name: &str) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+
+    if let Some(boxed) = jni_type_to_boxed_primitive(jni_type) {
+        let transformation = transform_rust_to_jni_type(jni_type, param_name, false);
+        let class = boxed.class;
+        let box_method = boxed.box_method;
+        let signature = format!("({})L{};", boxed.signature, boxed.class);
+        let error_msg = format!("Could not box {param_name} into {class}");
+
+        return quote! {
+            #transformation
+
+            let #param: jni::objects::JObject = {
+                let mut env = rc_env.borrow_mut();
+                let values: &[jni::objects::JValue] = &[#param.into()];
+                env.call_static_method(#class, #box_method, #signature, values)
+                    .and_then(|value| value.l())
+                    .expect(#error_msg)
+            };
+        };
+    }
+
+    match jni_type {
+        JniType::Option(ty) => {
+            let transformation = transform_rust_to_jobject(ty, param_name);
+
             quote! {
-                let #param: jni::objects::JObjectArray = {
-                    let mut env = rc_env.borrow_mut();
-                    let arr = env.new_object_array(#param.len(), "java/lang/String", "").unwrap();
-                    for (i, el) in #param.iter().enumerate() {
-                        #individual_item_transformation
+                let #param: jni::objects::JObject = match #param {
+                    None => jni::objects::JObject::null(),
+                    Some(#param) => {
+                        #transformation
 
-                        env.set_object_array_element(arr, i, el);
+                        #param
                     }
-
-                    arr
                 };
             }
         }
-        JniType::CustomType(_) => transform_custom_to_jobject(param_name, is_optional),
-        JniType::Receiver(_) => todo!(),
-        JniType::Option(ty) => transform_rust_to_jni_type(ty, param_name, true, false),
-        JniType::Interface(_) => panic!("Transformation from Rust traits to interfaces is not supported"),
-        JniType::Void => panic!("Void type can't be transformed"),
+        _ => {
+            let transformation = transform_rust_to_jni_type(jni_type, param_name, false);
+
+            quote! {
+                #transformation
+
+                let #param: jni::objects::JObject = #param.into();
+            }
+        }
     }
 }
 
-fn transform_jint_to_i32(param_name: &str, is_optional: bool) -> TokenStream2 {
-    if is_optional {
-        let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+pub fn transform_jobject_to_rust(jni_type: &JniType, param_name: &str, is_borrow: bool) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+
+    if let Some(boxed) = jni_type_to_boxed_primitive(jni_type) {
+        let unbox_method = boxed.unbox_method;
+        let signature = format!("(){}", boxed.signature);
+        let error_msg = format!("Could not unbox {param_name} from {}", boxed.class);
+        let jvalue_transformation = transform_jvalue_to_jni_type(jni_type, param_name);
+        let transformation = transform_jni_type_to_rust(jni_type, param_name, is_borrow);
 
-        let q = quote! {
+        return quote! {
             let #param = {
                 let mut env = rc_env.borrow_mut();
-                let value = env.get_field(&#param, "value", "I")
-                    .expect("Could not find field pointer")
-                    .i()
-                    .expect("Could not transform \"value\" to jint");
-
-                value
+                env.call_method(&#param, #unbox_method, #signature, &[]).expect(#error_msg)
             };
+            #jvalue_transformation
+            #transformation
         };
+    }
 
-        q
-    } else {
-        transform_types(param_name, quote! { i32 })
+    match jni_type {
+        JniType::String => {
+            let transformation = transform_jni_type_to_rust(jni_type, param_name, is_borrow);
+
+            quote! {
+                let #param = jni::objects::JString::from(#param);
+                #transformation
+            }
+        }
+        JniType::ByteArray => {
+            let transformation = transform_jni_type_to_rust(jni_type, param_name, is_borrow);
+
+            quote! {
+                let #param = jni::objects::JByteArray::from(#param);
+                #transformation
+            }
+        }
+        JniType::Vec(_) => {
+            let transformation = transform_jni_type_to_rust(jni_type, param_name, is_borrow);
+
+            quote! {
+                let #param = jni::objects::JObjectArray::from(#param);
+                #transformation
+            }
+        }
+        JniType::PrimitiveArray(ty) => {
+            let array_type = jni_type_to_primitive_array(ty).array_type;
+            let transformation = transform_jni_type_to_rust(jni_type, param_name, is_borrow);
+
+            quote! {
+                let #param = #array_type::from(#param);
+                #transformation
+            }
+        }
+        JniType::Option(ty) => {
+            let transformation = transform_jobject_to_rust(ty, param_name, is_borrow);
+
+            quote! {
+                let #param = if #param.is_null() {
+                    None
+                } else {
+                    #transformation
+
+                    Some(#param)
+                };
+            }
+        }
+        _ => transform_jni_type_to_rust(jni_type, param_name, is_borrow),
     }
 }
 
-fn transform_jlong_to_i64(param_name: &str) -> TokenStream2 {
-    transform_types(param_name, quote! { i64 })
+pub fn transform_jvalue_to_jni_type(jni_type: &JniType, param_name: &str) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let error_msg = format!("Could not get the value of {param_name}");
+
+    match jni_type {
+        JniType::Int8 => quote! { let #param = #param.b().expect(#error_msg); },
+        JniType::Int16 => quote! { let #param = #param.s().expect(#error_msg); },
+        JniType::Int32 => quote! { let #param = #param.i().expect(#error_msg); },
+        JniType::Int64 | JniType::UInt64 | JniType::ISize | JniType::USize => quote! { let #param = #param.j().expect(#error_msg); },
+        JniType::Char => quote! { let #param = #param.i().expect(#error_msg); },
+        JniType::UInt8 => quote! { let #param = #param.b().expect(#error_msg); },
+        JniType::UInt16 => quote! { let #param = #param.s().expect(#error_msg); },
+        JniType::UInt32 => quote! { let #param = #param.i().expect(#error_msg); },
+        JniType::Float32 => quote! { let #param = #param.f().expect(#error_msg); },
+        JniType::Float64 => quote! { let #param = #param.d().expect(#error_msg); },
+        JniType::Boolean => quote! { let #param = #param.z().expect(#error_msg); },
+        JniType::String => quote! {
+            let #param: jni::objects::JString = #param.l().expect(#error_msg).into();
+        },
+        JniType::ByteArray => quote! {
+            let #param: jni::objects::JByteArray = #param.l().expect(#error_msg).into();
+        },
+        JniType::CustomType(_) | JniType::Enum(_) | JniType::Interface(_) | JniType::Option(_) |
+        JniType::HashSet(_) | JniType::BTreeSet(_) | JniType::HashMap(..) | JniType::BTreeMap(..) |
+        JniType::Tuple(_) => quote! {
+            let #param = #param.l().expect(#error_msg);
+        },
+        JniType::Vec(_) => quote! {
+            let #param: jni::objects::JObjectArray = #param.l().expect(#error_msg).into();
+        },
+        JniType::PrimitiveArray(ty) => {
+            let array_type = jni_type_to_primitive_array(ty).array_type;
+
+            quote! {
+                let #param: #array_type = #param.l().expect(#error_msg).into();
+            }
+        }
+        JniType::Receiver(_) | JniType::Void | JniType::Result(_) | JniType::DirectBuffer | JniType::RustBuffer => {
+            unreachable!("Receiver, Void, Result and buffers can't be read from a JValue")
+        }
+    }
 }
 
-fn transform_jfloat_to_f32(param_name: &str) -> TokenStream2 {
-    transform_types(param_name, quote! { f64 })
+pub fn transform_jlong_to_receiver(param_name: &str, ty: &str, is_mutable: bool) -> TokenStream2 {
+    let param: TokenStream2 = syn::parse_str(param_name).unwrap();
+    let pointer = class_pointer(param_name);
+    let class_guard = class_guard(quote! { #pointer }, ty, is_mutable);
+    let mutability = is_mutable.then(|| quote! { mut });
+
+    quote! {
+        let #pointer = #param;
+        let #mutability #param = #class_guard;
+    }
 }
 
-fn transform_jdouble_to_f64(param_name: &str) -> TokenStream2 {
-    transform_types(param_name, quote! { f64 })
+/// Class borrowed by a function, either by its receiver or by a parameter
+pub struct BorrowedClass {
+    pub param_name: String,
+    pub ty: String,
+    pub is_mutable: bool,
 }
 
-fn transform_jlong_to_u64(param_name: &str) -> TokenStream2 {
-    transform_types(param_name, quote! { u64 })
+fn class_pointer(param_name: &str) -> TokenStream2 {
+    syn::parse_str(&format!("{param_name}_pointer")).unwrap()
 }
 
-fn transform_i32_to_jint(param_name: &str, is_optional: bool) -> TokenStream2 {
-    if is_optional {
-        let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+/// Locks of the same instance can't be taken twice by the same thread, so an instance borrowed by the receiver or
+/// an earlier parameter is rejected when one of the borrows is mutable, and reused when both are shared. Instances
+/// of zero-sized classes share their pointer without being the same, so they are never rejected.
+fn same_instance_borrows(param_name: &str, ty: &str, is_mutable: bool, borrowed_classes: &[BorrowedClass]) -> (TokenStream2, Vec<String>) {
+    let pointer = class_pointer(param_name);
+    let same_classes = borrowed_classes
+        .iter()
+        .filter(|borrowed| borrowed.ty == ty)
+        .collect::<Vec<&BorrowedClass>>();
+    let ty_token: TokenStream2 = syn::parse_str(ty).unwrap();
+
+    let checks = same_classes
+        .iter()
+        .filter(|borrowed| is_mutable || borrowed.is_mutable)
+        .map(|borrowed| {
+            let other_pointer = class_pointer(&borrowed.param_name);
+            let other_name = if borrowed.param_name == "jobject" { "self" } else { borrowed.param_name.as_str() };
+            let error_msg = format!("{param_name} is the same {ty} instance as {other_name}, which can't be borrowed mutably by both or while it's borrowed");
 
-        quote! {
-            let #param = {
-                match #param {
-                    None => {
-                        jni::objects::JObject::null()
-                    }
-                    Some(i) => {
-                        let mut env = rc_env.borrow_mut();
-                        let values: &[jni::objects::JValue] = &[i.into()];
-                        let jv = env
-                            .call_static_method("java/lang/Integer", "valueOf", "(I)Ljava/lang/Integer;", values)
-                            .expect("Unable to load ValueOf from java.lang.Integer");
-
-                        jv.l().expect("Could not get Integer type from valueOf result")
-                    }
+            quote! {
+                if #pointer == #other_pointer && std::mem::size_of::<<#ty_token as crate::JniClass>::Cell>() != 0 {
+                    crate::throw_illegal_argument(#error_msg.to_string());
                 }
-            };
-        }
+            }
+        })
+        .collect::<TokenStream2>();
+
+    let shared_borrows = if is_mutable {
+        Vec::new()
     } else {
-        transform_types(param_name, quote! { jni::sys::jint })
+        same_classes
+            .iter()
+            .filter(|borrowed| !borrowed.is_mutable)
+            .map(|borrowed| borrowed.param_name.clone())
+            .collect()
+    };
+
+    (checks, shared_borrows)
+}
+
+/// Guard of the instance of a class owned by a Kotlin object, which locks it for writing when it's borrowed mutably
+fn class_guard(pointer: TokenStream2, ty: &str, is_mutable: bool) -> TokenStream2 {
+    let ty: TokenStream2 = syn::parse_str(ty).unwrap();
+    let access = if is_mutable { quote! { write } } else { quote! { read } };
+
+    quote! {
+        {
+            let cell = unsafe { &*(#pointer as *const <#ty as crate::JniClass>::Cell) };
+            crate::JniClassCell::#access(cell)
+        }
     }
 }
 
+pub fn transform_rust_to_jni_type(jni_type: &JniType, param_name: &str, is_nested_transformation: bool) -> TokenStream2 {
+    match jni_type {
+        JniType::Int8 => transform_types(param_name, quote! { jni::sys::jbyte }),
+        JniType::Int16 => transform_types(param_name, quote! { jni::sys::jshort }),
+        JniType::Int32 => transform_i32_to_jint(param_name),
+        JniType::Int64 => transform_i64_to_jlong(param_name),
+        JniType::ISize | JniType::USize => transform_checked_types(param_name, quote! { jni::sys::jlong }),
+        JniType::Char => transform_char_to_jint(param_name),
+        JniType::UInt8 => transform_types(param_name, quote! { jni::sys::jbyte }),
+        JniType::UInt16 => transform_types(param_name, quote! { jni::sys::jshort }),
+        JniType::UInt32 => transform_types(param_name, quote! { jni::sys::jint }),
+        JniType::UInt64 => transform_types(param_name, quote! { jni::sys::jlong }),
+        JniType::Float32 => transform_f32_to_jfloat(param_name),
+        JniType::Float64 => transform_f64_to_jdouble(param_name),
+        JniType::String => transform_string_to_jstring(param_name, is_nested_transformation),
+        JniType::Boolean => transform_bool_to_jbool(param_name),
+        JniType::ByteArray => {
+            let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
 
-fn transform_i64_to_jlong(param_name: &str) -> TokenStream2 {
-    transform_types(param_name, quote! { jni::sys::jlong })
+            quote! {
+                let #param: jni::objects::JByteArray = {
+                    let mut env = rc_env.borrow_mut();
+                    #param.into_env(&mut env)
+                };
+            }
+        }
+        JniType::Vec(ty) => transform_vec_to_jarray(param_name, ty),
+        JniType::PrimitiveArray(ty) => transform_primitive_vec_to_jarray(param_name, ty),
+        JniType::HashSet(ty) => transform_set_to_jset(param_name, "java/util/LinkedHashSet", ty),
+        JniType::BTreeSet(ty) => transform_set_to_jset(param_name, "java/util/TreeSet", ty),
+        JniType::Tuple(element_types) => transform_tuple_to_jobject(param_name, element_types),
+        JniType::HashMap(key_ty, value_ty) => transform_map_to_jmap(param_name, "java/util/LinkedHashMap", key_ty, value_ty),
+        JniType::BTreeMap(key_ty, value_ty) => transform_map_to_jmap(param_name, "java/util/TreeMap", key_ty, value_ty),
+        JniType::CustomType(_) | JniType::Enum(_) | JniType::RustBuffer => transform_custom_to_jobject(param_name),
+        JniType::Option(_) => transform_rust_to_jobject(jni_type, param_name),
+        JniType::Receiver(_) | JniType::Interface(_) | JniType::Void | JniType::DirectBuffer => {
+            unreachable!("Receivers, interfaces, Void and borrowed buffers are rejected by the validation of the macro input")
+        }
+        JniType::Result(_) => unreachable!("Result must be unwrapped before being transformed"),
+    }
 }
 
-fn transform_u64_to_jlong(param_name: &str) -> TokenStream2 {
+fn transform_jint_to_i32(param_name: &str) -> TokenStream2 {
+    transform_types(param_name, quote! { i32 })
+}
+
+fn transform_jlong_to_i64(param_name: &str) -> TokenStream2 {
+    transform_types(param_name, quote! { i64 })
+}
+
+fn transform_jfloat_to_f32(param_name: &str) -> TokenStream2 {
+    transform_types(param_name, quote! { f32 })
+}
+
+fn transform_jdouble_to_f64(param_name: &str) -> TokenStream2 {
+    transform_types(param_name, quote! { f64 })
+}
+
+fn transform_i32_to_jint(param_name: &str) -> TokenStream2 {
+    transform_types(param_name, quote! { jni::sys::jint })
+}
+
+fn transform_i64_to_jlong(param_name: &str) -> TokenStream2 {
     transform_types(param_name, quote! { jni::sys::jlong })
 }
 
@@ -221,49 +777,160 @@ fn transform_bool_to_jbool(param_name: &str) -> TokenStream2 {
     transform_types(param_name, quote! { jni::sys::jboolean })
 }
 
-fn transform_custom_to_jobject(param_name: &str, is_optional: bool) -> TokenStream2 {
+fn transform_custom_to_jobject(param_name: &str) -> TokenStream2 {
     let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
 
-    if is_optional {
-        let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
-        let object_transformation = transform_custom_to_jobject(param_name, false);
-        quote! {
-            let #param = {
-                match #param {
-                    None => {
-                        jni::objects::JObject::null()
-                    }
-                    Some(#param) => {
-                        #object_transformation
+    quote! {
+        let mut #param = {
+            let mut env = rc_env.borrow_mut();
+            #param.into_env(&mut env)
+        };
+    }
+}
 
-                        #param
-                    }
-                }
-            };
+/// Borrows the memory of a direct `ByteBuffer`, which stays valid while the JVM keeps the buffer alive
+/// The slice covers the remaining bytes of the buffer, from its position to its limit, like the ones read by its
+/// relative `get` methods
+pub fn transform_jbytebuffer_to_slice(param_name: &str, is_mutable: bool) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let error_msg = format!("{param_name} must be a direct ByteBuffer");
+    let read_only_error_msg = format!("{param_name} is a read-only ByteBuffer and can't be borrowed mutably");
+
+    let (slice_type, empty_slice, from_raw_parts, read_only_check) = if is_mutable {
+        let read_only_check = quote! {
+            let is_read_only = env.call_method(&#param, "isReadOnly", "()Z", &[])
+                .and_then(|value| value.z())
+                .expect("Could not check if the ByteBuffer is read-only");
+            if is_read_only {
+                crate::throw_illegal_argument(#read_only_error_msg.to_string());
+            }
+        };
+
+        (quote! { &mut [u8] }, quote! { &mut [] }, quote! { std::slice::from_raw_parts_mut }, read_only_check)
+    } else {
+        (quote! { &[u8] }, quote! { &[] }, quote! { std::slice::from_raw_parts }, quote! {})
+    };
+
+    quote! {
+        let #param: #slice_type = {
+            let mut env = rc_env.borrow_mut();
+            // Checked first, as the address of the buffer is only valid for direct buffers
+            if env.get_direct_buffer_capacity(&#param).is_err() {
+                crate::throw_illegal_argument(#error_msg.to_string());
+            }
+            #read_only_check
+
+            let position = env.call_method(&#param, "position", "()I", &[])
+                .and_then(|value| value.i())
+                .expect("Could not get the position of the ByteBuffer") as usize;
+            let limit = env.call_method(&#param, "limit", "()I", &[])
+                .and_then(|value| value.i())
+                .expect("Could not get the limit of the ByteBuffer") as usize;
+
+            // Empty buffers may not have an address
+            if position == limit {
+                #empty_slice
+            } else {
+                let address = env.get_direct_buffer_address(&#param).expect(#error_msg);
+                unsafe { #from_raw_parts(address.add(position), limit - position) }
+            }
+        };
+    }
+}
+
+/// Interfaces taken by value are wrapped in their global bridge, which only persistent interfaces have. The bridge is
+/// created with the span of the parameter, so rustc points at it when the interface isn't persistent.
+pub fn transform_jobject_to_global_interface(param_name: &str, interface_name: &str, span: Span) -> TokenStream2 {
+    let param = syn::Ident::new(param_name, span);
+    let bridge = syn::Ident::new(&format!("{interface_name}JniBridge"), span);
+
+    quote_spanned! { span =>
+        let #param = {
+            let env = rc_env.borrow();
+            <#bridge<'static> as crate::JniPersistentInterface>::global_bridge(&env, &#param)
+        };
+    }
+}
+
+pub fn transform_jobject_to_class(param_name: &str, ty: &str, is_mutable: bool, borrowed_classes: &[BorrowedClass]) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let pointer = class_pointer(param_name);
+    let read_pointer = transform_jobject_to_pointer(&param);
+    let class_guard = class_guard(quote! { #pointer }, ty, is_mutable);
+    let mutability = is_mutable.then(|| quote! { mut });
+    let (checks, shared_borrows) = same_instance_borrows(param_name, ty, is_mutable, borrowed_classes);
+
+    let borrow = if shared_borrows.is_empty() {
+        quote! {
+            let #mutability #param = #class_guard;
         }
     } else {
+        let guard = syn::parse_str::<TokenStream2>(&format!("{param_name}_guard")).unwrap();
+        let shared_pointers = shared_borrows
+            .iter()
+            .map(|borrowed| class_pointer(borrowed))
+            .collect::<Vec<TokenStream2>>();
+        let shared_borrows = shared_borrows
+            .iter()
+            .map(|borrowed| syn::parse_str::<TokenStream2>(borrowed).unwrap())
+            .collect::<Vec<TokenStream2>>();
+
         quote! {
-            let mut #param = {
-                let mut env = rc_env.borrow_mut();
-                #param.into_env(&mut env)
+            let #guard = if #(#pointer == #shared_pointers)||* {
+                None
+            } else {
+                Some(#class_guard)
+            };
+            let #param = match &#guard {
+                Some(guard) => &**guard,
+                #(None if #pointer == #shared_pointers => &*#shared_borrows,)*
+                None => unreachable!(),
             };
         }
+    };
+
+    quote! {
+        let #pointer = #read_pointer;
+        #checks
+        #borrow
     }
 }
 
-fn transform_jobject_to_custom(param_name: &str, ty: &str) -> TokenStream2 {
+/// Classes returned borrowed by interfaces can't be locked past the call, so only unsynchronized ones are supported
+pub fn transform_jobject_to_unsynchronized_class(param_name: &str, ty: &str) -> TokenStream2 {
     let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let pointer = transform_jobject_to_pointer(&param);
+    let ty = syn::parse_str::<TokenStream2>(ty).unwrap();
 
-    let ty = syn::parse_str::<TokenStream2>(&ty).unwrap();
     quote! {
-        let mut #param = {
+        let #param = {
+            let pointer = #pointer;
+            unsafe { crate::unsynchronized_class::<_, #ty>(self, pointer) }
+        };
+    }
+}
+
+fn transform_jobject_to_pointer(param: &TokenStream2) -> TokenStream2 {
+    quote! {
+        {
             let mut env = rc_env.borrow_mut();
-            let field = env.get_field(&#param, "pointer", "J")
+            env.get_field(&#param, "pointer", "J")
                 .expect("Could not find field pointer")
                 .j()
-                .expect("Could not transform pointer to jlong");
-            unsafe { &mut *(field as *mut #ty) }
-        }; 
+                .expect("Could not transform pointer to jlong")
+        }
+    }
+}
+
+fn transform_jobject_to_value(param_name: &str, ty: &str) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let ty = syn::parse_str::<TokenStream2>(ty).unwrap();
+
+    quote! {
+        let #param: #ty = {
+            let mut env = rc_env.borrow_mut();
+            crate::IntoEnv::<'_, #ty>::into_env(#param, &mut env)
+        };
     }
 }
 
@@ -281,22 +948,46 @@ fn transform_types(param_name: &str, target_type: TokenStream2) -> TokenStream2
     quote! { let #param = #param as #target_type; }
 }
 
-fn transform_jstring_to_string(param_name: &str, is_optional: bool) -> TokenStream2 {
-    let param_to_get_string = if is_optional {
-        format!("{param_name}.into()")
-    } else {
-        param_name.to_string()
-    };
+fn transform_checked_types(param_name: &str, target_type: TokenStream2) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+    let target_type_name = target_type.to_string().replace(' ', "");
+    let error_msg = format!("Value {{}} of {param_name} does not fit in {target_type_name}");
 
+    quote! {
+        let #param = <#target_type>::try_from(#param).unwrap_or_else(|_| panic!(#error_msg, #param));
+    }
+}
+
+/// Chars are Kotlin `Int` code points, as a Kotlin `Char` can't hold the chars outside the Basic Multilingual Plane
+fn transform_jint_to_char(param_name: &str) -> TokenStream2 {
     let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
-    let param_to_get_string = syn::parse_str::<TokenStream2>(&param_to_get_string).unwrap();
+    let error_msg = format!("Value {{:#x}} of {param_name} is not a Unicode scalar value and can't be converted to char");
 
-    quote! {        
+    quote! {
+        let #param = u32::try_from(#param)
+            .ok()
+            .and_then(char::from_u32)
+            .unwrap_or_else(|| crate::throw_illegal_argument(format!(#error_msg, #param)));
+    }
+}
+
+fn transform_char_to_jint(param_name: &str) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+
+    quote! {
+        let #param = u32::from(#param) as jni::sys::jint;
+    }
+}
+
+fn transform_jstring_to_string(param_name: &str) -> TokenStream2 {
+    let param = syn::parse_str::<TokenStream2>(param_name).unwrap();
+
+    quote! {
         let #param: String = {
             let mut env = rc_env.borrow_mut();
-            
+
             env
-                .get_string(&#param_to_get_string)
+                .get_string(&#param)
                 .expect("Couldn't get java string!")
                 .into()
         };
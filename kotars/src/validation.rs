@@ -0,0 +1,594 @@
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned, ToTokens};
+use syn::spanned::Spanned;
+use syn::{
+    Fields, FnArg, GenericArgument, Generics, ImplItem, ItemEnum, ItemImpl, ItemStruct, ItemTrait, Pat, PathArguments,
+    ReturnType, Signature, TraitItem, Type,
+};
+
+use crate::type_resolver;
+
+/// Place of a type in the item given to a macro, which determines the types that can be used
+#[derive(Clone, Copy, PartialEq)]
+pub enum TypePosition {
+    FunctionParameter,
+    FunctionReturn,
+    InterfaceParameter,
+    InterfaceReturn,
+    DataClassField,
+    SealedClassField,
+}
+
+impl TypePosition {
+    fn description(&self) -> &'static str {
+        match self {
+            TypePosition::FunctionParameter => "#[jni_struct_impl] parameter",
+            TypePosition::FunctionReturn => "#[jni_struct_impl] return",
+            TypePosition::InterfaceParameter => "#[jni_interface] parameter",
+            TypePosition::InterfaceReturn => "#[jni_interface] return",
+            TypePosition::DataClassField => "#[jni_data_class] field",
+            TypePosition::SealedClassField => "#[jni_sealed_class] field",
+        }
+    }
+
+    fn is_parameter(&self) -> bool {
+        matches!(self, TypePosition::FunctionParameter | TypePosition::InterfaceParameter)
+    }
+
+    fn is_return(&self) -> bool {
+        matches!(self, TypePosition::FunctionReturn | TypePosition::InterfaceReturn)
+    }
+}
+
+const PRIMITIVE_TYPES: [&str; 15] = [
+    "i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64", "isize", "usize", "f32", "f64", "bool", "char", "String",
+];
+
+/// Collects every error of an item, so all of them are reported in a single compilation
+#[derive(Default)]
+struct Errors(Option<syn::Error>);
+
+impl Errors {
+    fn push(&mut self, result: syn::Result<()>) {
+        if let Err(err) = result {
+            match &mut self.0 {
+                Some(errors) => errors.combine(err),
+                None => self.0 = Some(err),
+            }
+        }
+    }
+
+    fn finish(self) -> syn::Result<()> {
+        match self.0 {
+            Some(errors) => Err(errors),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn validate_struct_impl(item_impl: &ItemImpl) -> syn::Result<()> {
+    let mut errors = Errors::default();
+
+    errors.push(validate_no_generics(&item_impl.generics, "#[jni_struct_impl]"));
+    errors.push(validate_self_type(&item_impl.self_ty));
+
+    for item in &item_impl.items {
+        if let ImplItem::Fn(method) = item {
+            errors.push(validate_signature(&method.sig, TypePosition::FunctionParameter, TypePosition::FunctionReturn));
+
+            if method.sig.asyncness.is_some() {
+                errors.push(validate_async_parameters(&method.sig));
+            }
+        }
+    }
+
+    errors.finish()
+}
+
+pub fn validate_interface(item_trait: &ItemTrait) -> syn::Result<()> {
+    let mut errors = Errors::default();
+
+    errors.push(validate_no_generics(&item_trait.generics, "#[jni_interface]"));
+
+    for item in &item_trait.items {
+        if let TraitItem::Fn(method) = item {
+            if let Some(asyncness) = &method.sig.asyncness {
+                errors.push(Err(syn::Error::new_spanned(asyncness, "async functions are not supported by #[jni_interface]")));
+            }
+
+            errors.push(validate_signature(&method.sig, TypePosition::InterfaceParameter, TypePosition::InterfaceReturn));
+        }
+    }
+
+    errors.finish()
+}
+
+pub fn validate_class(item_struct: &ItemStruct) -> syn::Result<()> {
+    // Fields of classes stay in Rust, so any type can be used
+    validate_no_generics(&item_struct.generics, "#[jni_class]")
+}
+
+pub fn validate_data_class(item_struct: &ItemStruct) -> syn::Result<()> {
+    let mut errors = Errors::default();
+
+    errors.push(validate_no_generics(&item_struct.generics, "#[jni_data_class]"));
+    errors.push(validate_fields(&item_struct.fields, TypePosition::DataClassField));
+
+    errors.finish()
+}
+
+pub fn validate_enum(item_enum: &ItemEnum) -> syn::Result<()> {
+    let mut errors = Errors::default();
+
+    errors.push(validate_no_generics(&item_enum.generics, "#[jni_enum]"));
+
+    for variant in &item_enum.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            let message = format!(
+                "variant `{}` of enum `{}` has fields, only fieldless enums can be used with #[jni_enum], use #[jni_sealed_class] instead",
+                variant.ident, item_enum.ident,
+            );
+            errors.push(Err(syn::Error::new_spanned(&variant.fields, message)));
+        }
+    }
+
+    errors.finish()
+}
+
+pub fn validate_sealed_class(item_enum: &ItemEnum) -> syn::Result<()> {
+    let mut errors = Errors::default();
+
+    errors.push(validate_no_generics(&item_enum.generics, "#[jni_sealed_class]"));
+
+    for variant in &item_enum.variants {
+        errors.push(validate_fields(&variant.fields, TypePosition::SealedClassField));
+    }
+
+    errors.finish()
+}
+
+fn validate_no_generics(generics: &Generics, macro_name: &str) -> syn::Result<()> {
+    if generics.params.is_empty() {
+        Ok(())
+    } else {
+        Err(syn::Error::new_spanned(generics, format!("generic items are not supported by {macro_name}")))
+    }
+}
+
+/// Functions are called through the type the bindings are generated for, so it must be named by a plain identifier
+fn validate_self_type(ty: &Type) -> syn::Result<()> {
+    match ty {
+        Type::Path(type_path) if type_path.qself.is_none() && type_path.path.get_ident().is_some() => Ok(()),
+        ty => Err(syn::Error::new_spanned(
+            ty,
+            format!("#[jni_struct_impl] can only be used on the impl of a type named by its identifier, found `{}`", type_name(ty)),
+        )),
+    }
+}
+
+fn validate_fields(fields: &Fields, position: TypePosition) -> syn::Result<()> {
+    let mut errors = Errors::default();
+
+    for field in fields {
+        errors.push(validate_type(&field.ty, position));
+    }
+
+    errors.finish()
+}
+
+fn validate_signature(sig: &Signature, parameter_position: TypePosition, return_position: TypePosition) -> syn::Result<()> {
+    let mut errors = Errors::default();
+
+    // Bindings call the functions of classes without naming their lifetimes, but the bridges of interfaces
+    // implement them with the signature of the trait, which doesn't include its generics
+    let has_generic_types = sig.generics.params
+        .iter()
+        .any(|param| !matches!(param, syn::GenericParam::Lifetime(_)));
+    if has_generic_types || (parameter_position == TypePosition::InterfaceParameter && !sig.generics.params.is_empty()) {
+        errors.push(Err(syn::Error::new_spanned(&sig.generics, format!("generic functions are not supported in {}", item_macro_name(parameter_position)))));
+    }
+
+    for input in &sig.inputs {
+        match input {
+            FnArg::Receiver(receiver) => {
+                if receiver.reference.is_none() {
+                    errors.push(Err(syn::Error::new_spanned(
+                        receiver,
+                        "functions must not take ownership of `self` to prevent crashes in the JVM after freeing the memory, use `&self` or `&mut self`",
+                    )));
+                }
+            }
+            FnArg::Typed(pat_type) => {
+                errors.push(validate_parameter_name(&pat_type.pat));
+                errors.push(validate_type(&pat_type.ty, parameter_position));
+            }
+        }
+    }
+
+    if let ReturnType::Type(_, ty) = &sig.output {
+        errors.push(validate_type(ty, return_position));
+    }
+
+    errors.finish()
+}
+
+/// The futures of async functions complete on a worker of the executor after the function returns, so they can't
+/// borrow the instance owned by the Kotlin object nor any value converted from the JVM, which only lives until then
+fn validate_async_parameters(sig: &Signature) -> syn::Result<()> {
+    let mut errors = Errors::default();
+
+    for input in &sig.inputs {
+        match input {
+            FnArg::Receiver(receiver) if receiver.reference.is_some() => {
+                errors.push(Err(syn::Error::new_spanned(
+                    receiver,
+                    "async functions can't borrow `self`, as the Kotlin object owning the instance may be closed before the future completes, take the values it needs as parameters instead",
+                )));
+            }
+            FnArg::Receiver(_) => {}
+            FnArg::Typed(pat_type) => {
+                let Ok(Type::Reference(reference)) = type_resolver::resolve_type(&pat_type.ty) else {
+                    continue;
+                };
+
+                let reason = match reference.elem.as_ref() {
+                    // The Kotlin side may free the memory of the buffer before the future completes
+                    elem if is_direct_buffer(elem) => "as the buffer may be freed while the function runs",
+                    Type::ImplTrait(_) => "take the interface by value with `Send + 'static` bounds instead",
+                    _ => "take an owned value instead",
+                };
+
+                errors.push(Err(syn::Error::new_spanned(
+                    &pat_type.ty,
+                    format!("type `{}` can't be borrowed by async functions, {reason}", type_name(&pat_type.ty)),
+                )));
+            }
+        }
+    }
+
+    errors.finish()
+}
+
+/// Asserts that the custom types converted from Kotlin values, like the elements of a `Vec`, are not #[jni_class]
+/// structs, which stay owned by their Kotlin object, and that the custom types borrowed from a Kotlin object are
+/// #[jni_class] structs. The kind of a custom type can't be known by the macro that uses it, so the checks are
+/// left to the `JniValue` and `JniClass` traits.
+pub fn type_assertions<'a>(types: impl IntoIterator<Item = (&'a Type, TypePosition)>) -> TokenStream {
+    let mut value_types = Vec::new();
+    let mut class_types = Vec::new();
+
+    for (ty, position) in types {
+        let Ok(ty) = type_resolver::resolve_type(ty) else {
+            continue;
+        };
+
+        match &ty {
+            // Classes are borrowed by parameters of functions and returned borrowed by interfaces
+            Type::Reference(reference)
+                if matches!(position, TypePosition::FunctionParameter | TypePosition::InterfaceReturn) && is_custom_type(&reference.elem) => {
+                class_types.push(reference.elem.as_ref().clone());
+            }
+            ty => collect_custom_types(ty, &mut value_types),
+        }
+    }
+
+    if value_types.is_empty() && class_types.is_empty() {
+        return TokenStream::new();
+    }
+
+    let value_assertions = value_types
+        .iter()
+        .map(|ty| quote_spanned! { ty.span() => assert_jni_value::<#ty>(); });
+    let class_assertions = class_types
+        .iter()
+        .map(|ty| quote_spanned! { ty.span() => assert_jni_class::<#ty>(); });
+
+    quote! {
+        const _: fn() = || {
+            fn assert_jni_value<T: crate::JniValue>() {}
+            fn assert_jni_class<T: crate::JniClass>() {}
+            #(#value_assertions)*
+            #(#class_assertions)*
+        };
+    }
+}
+
+fn collect_custom_types(ty: &Type, custom_types: &mut Vec<Type>) {
+    match ty {
+        ty if is_custom_type(ty) => custom_types.push(ty.clone()),
+        Type::Path(type_path) => {
+            for argument in generic_types(ty, type_path).unwrap_or_default() {
+                collect_custom_types(argument, custom_types);
+            }
+        }
+        Type::Reference(reference) => collect_custom_types(&reference.elem, custom_types),
+        Type::Slice(slice) => collect_custom_types(&slice.elem, custom_types),
+        Type::Tuple(tuple) => tuple.elems.iter().for_each(|element| collect_custom_types(element, custom_types)),
+        _ => {}
+    }
+}
+
+/// Types defined by the crate, which are named by their identifier once the type is resolved
+fn is_custom_type(ty: &Type) -> bool {
+    const KNOWN_TYPES: [&str; 12] = [
+        "str", "Vec", "Option", "HashMap", "HashSet", "BTreeMap", "BTreeSet", "Cow", "Box", "Result", "DirectBuffer", "Self",
+    ];
+
+    matches!(
+        ty,
+        Type::Path(type_path) if type_path.qself.is_none() && type_path.path.get_ident().is_some_and(|ident| {
+            let name = ident.to_string();
+            !PRIMITIVE_TYPES.contains(&name.as_str()) && !KNOWN_TYPES.contains(&name.as_str())
+        })
+    )
+}
+
+/// The name of the parameter is reused by the generated code, so patterns can't be used
+fn validate_parameter_name(pat: &Pat) -> syn::Result<()> {
+    match pat {
+        Pat::Ident(pat_ident) if pat_ident.by_ref.is_none() && pat_ident.mutability.is_none() && pat_ident.subpat.is_none() => Ok(()),
+        pat => Err(syn::Error::new_spanned(pat, "parameters must be named by a plain identifier")),
+    }
+}
+
+fn item_macro_name(position: TypePosition) -> &'static str {
+    match position {
+        TypePosition::FunctionParameter | TypePosition::FunctionReturn => "#[jni_struct_impl]",
+        TypePosition::InterfaceParameter | TypePosition::InterfaceReturn => "#[jni_interface]",
+        TypePosition::DataClassField => "#[jni_data_class]",
+        TypePosition::SealedClassField => "#[jni_sealed_class]",
+    }
+}
+
+/// Checks the outermost type, which is the only one that can be borrowed, an interface, `()` or a `Result`
+fn validate_type(ty: &Type, position: TypePosition) -> syn::Result<()> {
+    validate_resolved_type(&type_resolver::resolve_type(ty)?, position)
+}
+
+fn validate_resolved_type(ty: &Type, position: TypePosition) -> syn::Result<()> {
+    match ty {
+        Type::Reference(reference) => validate_borrowed_type(ty, &reference.elem, reference.mutability.is_some(), position),
+        Type::Tuple(tuple) if tuple.elems.is_empty() => {
+            if position.is_return() {
+                Ok(())
+            } else {
+                Err(unsupported_type(ty, position, None))
+            }
+        }
+        Type::ImplTrait(impl_trait) => {
+            if position.is_parameter() {
+                validate_interface_bounds(impl_trait)
+            } else {
+                Err(unsupported_type(ty, position, Some("interfaces can only be used as parameters")))
+            }
+        }
+        ty if position == TypePosition::FunctionReturn && is_rust_buffer(ty) => Ok(()),
+        Type::Path(type_path) if last_segment_name(type_path).as_deref() == Some("Cow") && position.is_parameter() => {
+            validate_cow(ty, type_path, position)
+        }
+        Type::Path(type_path) if last_segment_name(type_path).as_deref() == Some("Result") => {
+            if position != TypePosition::FunctionReturn {
+                return Err(unsupported_type(ty, position, Some("`Result` can only be used as return type of functions")));
+            }
+
+            match generic_types(ty, type_path)?.first() {
+                Some(Type::Tuple(tuple)) if tuple.elems.is_empty() => Ok(()),
+                Some(ok_ty) if is_rust_buffer(ok_ty) => Ok(()),
+                Some(ok_ty) => validate_value_type(ok_ty, position),
+                None => Err(unsupported_type(ty, position, Some("`Result` must have an `Ok` type"))),
+            }
+        }
+        ty => validate_value_type(ty, position),
+    }
+}
+
+fn validate_borrowed_type(ty: &Type, elem: &Type, is_mutable: bool, position: TypePosition) -> syn::Result<()> {
+    let is_supported = match position {
+        TypePosition::FunctionParameter => match elem {
+            elem if is_str_or_slice(elem) || is_direct_buffer(elem) => true,
+            Type::ImplTrait(_) => true,
+            // Collections are copied from the Kotlin value, so the changes of a mutable borrow would be lost
+            Type::Path(type_path) => match last_segment_name(type_path).as_deref() {
+                Some("Vec" | "HashMap" | "HashSet" | "BTreeMap" | "BTreeSet") => !is_mutable,
+                Some(name) => !PRIMITIVE_TYPES.contains(&name) && name != "Option",
+                None => false,
+            },
+            Type::Tuple(tuple) => !tuple.elems.is_empty() && !is_mutable,
+            _ => false,
+        },
+        // Borrowed parameters are copied before being handed over to the JVM
+        TypePosition::InterfaceParameter => true,
+        // Only classes can be returned borrowed, as a reference to the instance owned by the Kotlin object
+        TypePosition::InterfaceReturn => matches!(
+            elem,
+            Type::Path(type_path) if type_path.path.get_ident().is_some_and(|ident| !PRIMITIVE_TYPES.contains(&ident.to_string().as_str()))
+        ),
+        // Strings and slices borrowed from the instance are copied into the returned Kotlin value
+        TypePosition::FunctionReturn => {
+            if is_mutable || !is_str_or_slice(elem) {
+                return Err(unsupported_type(ty, position, Some("only `&str` and slices of bytes or primitives can be returned borrowed, return an owned value instead")));
+            }
+
+            true
+        }
+        TypePosition::DataClassField | TypePosition::SealedClassField => false,
+    };
+
+    if !is_supported {
+        let reason = if is_mutable && validate_borrowed_type(ty, elem, false, position).is_ok() {
+            "it can't be borrowed mutably, as it's a copy of the Kotlin value and its changes would be lost"
+        } else {
+            "it can't be borrowed"
+        };
+
+        return Err(unsupported_type(ty, position, Some(reason)));
+    }
+
+    match elem {
+        Type::ImplTrait(impl_trait) => validate_interface_bounds(impl_trait),
+        elem if is_str_or_slice(elem) => Ok(()),
+        elem if is_direct_buffer(elem) && position == TypePosition::FunctionParameter => Ok(()),
+        elem => validate_value_type(elem, position),
+    }
+}
+
+/// `Cow<str>` is only accepted by parameters, which build it from the string received from the JVM
+fn validate_cow(ty: &Type, type_path: &syn::TypePath, position: TypePosition) -> syn::Result<()> {
+    let is_str = type_path.path.segments.last().is_some_and(|segment| match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) => {
+            let types = arguments.args
+                .iter()
+                .filter(|argument| !matches!(argument, GenericArgument::Lifetime(_)))
+                .collect::<Vec<&GenericArgument>>();
+
+            matches!(types.as_slice(), [GenericArgument::Type(Type::Path(str_path))] if str_path.path.is_ident("str"))
+        }
+        _ => false,
+    });
+
+    if is_str {
+        Ok(())
+    } else {
+        Err(unsupported_type(ty, position, Some("only `Cow<str>` is supported")))
+    }
+}
+
+/// Interfaces are identified by their first bound, any other bound like `Send` or `'static` is ignored. Whether an
+/// interface taken by value is persistent is only known by its #[jni_interface], so it's checked by rustc through
+/// the `JniPersistentInterface` bound of its global bridge, which is spanned at the parameter
+fn validate_interface_bounds(impl_trait: &syn::TypeImplTrait) -> syn::Result<()> {
+    match impl_trait.bounds.first() {
+        Some(syn::TypeParamBound::Trait(bound)) if bound.path.get_ident().is_some() => Ok(()),
+        _ => Err(syn::Error::new_spanned(
+            impl_trait,
+            "interfaces must be an `impl` of a #[jni_interface] trait named by its identifier, followed by any other bound",
+        )),
+    }
+}
+
+/// Checks a type that is converted from or to a value of the JVM, including the ones nested in generic types
+fn validate_value_type(ty: &Type, position: TypePosition) -> syn::Result<()> {
+    match ty {
+        Type::Tuple(tuple) if !tuple.elems.is_empty() => {
+            tuple.elems.iter().try_for_each(|element| validate_value_type(element, position))
+        }
+        Type::Path(type_path) if type_path.qself.is_none() => {
+            // Paths of the standard library types are removed when the type is resolved
+            if type_path.path.segments.len() > 1 {
+                return Err(unsupported_type(ty, position, Some("import it and use it by its name")));
+            }
+
+            let name = last_segment_name(type_path).unwrap_or_default();
+            let expected_arguments = match name.as_str() {
+                "Vec" | "Option" | "HashSet" | "BTreeSet" => 1,
+                "HashMap" | "BTreeMap" => 2,
+                "Result" => return Err(unsupported_type(ty, position, Some("`Result` can only be used as return type of functions"))),
+                "Self" => return Err(unsupported_type(ty, position, Some("use the name of the type instead"))),
+                "DirectBuffer" => {
+                    return Err(unsupported_type(ty, position, Some("`DirectBuffer` can only be borrowed by parameters of #[jni_struct_impl] functions")));
+                }
+                "Box" if is_rust_buffer(ty) => {
+                    return Err(unsupported_type(ty, position, Some("`Box<DirectBuffer>` can only be the return type of #[jni_struct_impl] functions, or the `Ok` type of their `Result`")));
+                }
+                "Cow" => return Err(unsupported_type(ty, position, Some("`Cow<str>` can only be used as the type of parameters"))),
+                "str" => return Err(unsupported_type(ty, position, Some("use `String` instead"))),
+                "i128" | "u128" => return Err(unsupported_type(ty, position, None)),
+                _ => 0,
+            };
+
+            let arguments = generic_types(ty, type_path)?;
+            if arguments.len() != expected_arguments {
+                let reason = if expected_arguments == 0 {
+                    "generic types other than `Vec`, `Option`, sets and maps are not supported".to_string()
+                } else {
+                    format!("`{name}` expects {expected_arguments} type argument(s)")
+                };
+
+                return Err(unsupported_type(ty, position, Some(&reason)));
+            }
+
+            arguments.iter().try_for_each(|argument| validate_value_type(argument, position))
+        }
+        Type::Tuple(_) => Err(unsupported_type(ty, position, Some("`()` can only be used as return type"))),
+        Type::Reference(_) => Err(unsupported_type(ty, position, Some("only the outermost type can be borrowed"))),
+        Type::ImplTrait(_) => Err(unsupported_type(ty, position, Some("interfaces can't be nested in other types"))),
+        ty => Err(unsupported_type(ty, position, None)),
+    }
+}
+
+fn generic_types<'a>(ty: &Type, type_path: &'a syn::TypePath) -> syn::Result<Vec<&'a Type>> {
+    let Some(segment) = type_path.path.segments.last() else {
+        return Ok(Vec::new());
+    };
+
+    match &segment.arguments {
+        PathArguments::None => Ok(Vec::new()),
+        PathArguments::AngleBracketed(arguments) => arguments.args
+            .iter()
+            .map(|argument| match argument {
+                GenericArgument::Type(ty) => Ok(ty),
+                argument => Err(syn::Error::new_spanned(argument, format!("only type arguments are supported in `{}`", type_name(ty)))),
+            })
+            .collect(),
+        PathArguments::Parenthesized(_) => Err(syn::Error::new_spanned(ty, format!("type `{}` is not supported", type_name(ty)))),
+    }
+}
+
+fn last_segment_name(type_path: &syn::TypePath) -> Option<String> {
+    type_path.path.segments.last().map(|segment| segment.ident.to_string())
+}
+
+/// `str` and slices copied from and into Kotlin arrays, which can only be used borrowed
+fn is_str_or_slice(ty: &Type) -> bool {
+    match ty {
+        Type::Path(type_path) => type_path.qself.is_none() && type_path.path.is_ident("str"),
+        Type::Slice(slice) => is_array_element(&slice.elem),
+        _ => false,
+    }
+}
+
+/// `DirectBuffer`, the slice of bytes defined by `jni_init!` to share buffers with the JVM
+fn is_direct_buffer(ty: &Type) -> bool {
+    matches!(ty, Type::Path(type_path) if type_path.qself.is_none() && type_path.path.is_ident("DirectBuffer"))
+}
+
+/// `Box<DirectBuffer>`, which is returned to the JVM as a `RustBuffer`
+fn is_rust_buffer(ty: &Type) -> bool {
+    let Type::Path(type_path) = ty else {
+        return false;
+    };
+
+    type_path.qself.is_none()
+        && last_segment_name(type_path).as_deref() == Some("Box")
+        && matches!(generic_types(ty, type_path).as_deref(), Ok([elem]) if is_direct_buffer(elem))
+}
+
+/// Types that have their own Kotlin array, like `IntArray`
+fn is_array_element(ty: &Type) -> bool {
+    matches!(
+        ty,
+        Type::Path(type_path) if ["u8", "i8", "i16", "i32", "i64", "f32", "f64", "bool"]
+            .iter()
+            .any(|name| type_path.qself.is_none() && type_path.path.is_ident(name))
+    )
+}
+
+fn unsupported_type(ty: &Type, position: TypePosition, reason: Option<&str>) -> syn::Error {
+    let mut message = format!("type `{}` is not supported in a {} position", type_name(ty), position.description());
+
+    if let Some(reason) = reason {
+        message.push_str(", ");
+        message.push_str(reason);
+    }
+
+    syn::Error::new_spanned(ty, message)
+}
+
+/// Type as written in the source code, without the spaces added between its tokens
+fn type_name(ty: &Type) -> String {
+    let name = ty.to_token_stream().to_string();
+
+    [(" <", "<"), ("< ", "<"), (" >", ">"), (" ,", ","), (" :: ", "::"), (":: ", "::"), ("& ", "&"), ("( ", "("), (" )", ")")]
+        .iter()
+        .fold(name, |name, (from, to)| name.replace(from, to))
+}
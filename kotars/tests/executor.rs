@@ -0,0 +1,87 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+kotars::jni_init!("");
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Future completed by a thread of its own, which wakes it once it's done
+#[derive(Default)]
+struct ThreadSignal {
+    state: Arc<Mutex<(bool, Option<Waker>)>>,
+    started: bool,
+}
+
+impl Future for ThreadSignal {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.0 {
+            return Poll::Ready(());
+        }
+        state.1 = Some(context.waker().clone());
+        drop(state);
+
+        if !self.started {
+            self.started = true;
+            let state = Arc::clone(&self.state);
+
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                let mut state = state.lock().unwrap();
+                state.0 = true;
+                if let Some(waker) = state.1.take() {
+                    waker.wake();
+                }
+            });
+        }
+
+        Poll::Pending
+    }
+}
+
+struct DropSignal(Sender<()>);
+
+impl Drop for DropSignal {
+    fn drop(&mut self) {
+        let _ = self.0.send(());
+    }
+}
+
+#[test]
+fn future_woken_from_another_thread_completes_on_a_worker() {
+    let (sender, receiver) = channel();
+
+    let _task = spawn_jni_executor_task(async move {
+        ThreadSignal::default().await;
+        let worker = std::thread::current().name().map(str::to_string);
+        sender.send(worker).unwrap();
+    });
+
+    let worker = receiver.recv_timeout(TIMEOUT).expect("The future was not completed");
+    assert!(worker.is_some_and(|name| name.starts_with("kotars-worker-")));
+}
+
+#[test]
+fn cancelled_task_drops_its_future() {
+    let (polled_sender, polled_receiver) = channel();
+    let (dropped_sender, dropped_receiver) = channel();
+    let drop_signal = DropSignal(dropped_sender);
+
+    let task = spawn_jni_executor_task(async move {
+        let _drop_signal = drop_signal;
+        polled_sender.send(()).unwrap();
+        std::future::pending::<()>().await;
+    });
+
+    polled_receiver.recv_timeout(TIMEOUT).expect("The future was not polled");
+    assert!(dropped_receiver.try_recv().is_err());
+
+    cancel_jni_executor_task(&task);
+    dropped_receiver.recv_timeout(TIMEOUT).expect("The future was not dropped");
+}
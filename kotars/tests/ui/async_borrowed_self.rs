@@ -0,0 +1,15 @@
+use kotars::{jni_class, jni_init, jni_struct_impl};
+
+jni_init!("");
+
+#[jni_class]
+struct Downloader {}
+
+#[jni_struct_impl]
+impl Downloader {
+    async fn download(&self, _url: String) -> Vec<u8> {
+        Vec::new()
+    }
+}
+
+fn main() {}
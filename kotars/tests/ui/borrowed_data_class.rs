@@ -0,0 +1,19 @@
+use kotars::{jni_class, jni_data_class, jni_init, jni_struct_impl};
+
+jni_init!("");
+
+#[jni_data_class]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[jni_class]
+struct Canvas {}
+
+#[jni_struct_impl]
+impl Canvas {
+    fn draw(&self, _point: &Point) {}
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use kotars::{jni_class, jni_init, jni_struct_impl};
+
+jni_init!("");
+
+#[jni_class]
+struct Counter {}
+
+#[jni_struct_impl]
+impl Counter {
+    fn merge(&self, _other: Counter) {}
+}
+
+fn main() {}
@@ -0,0 +1,18 @@
+use kotars::{jni_class, jni_init, jni_interface, jni_struct_impl};
+
+jni_init!("");
+
+#[jni_interface]
+trait Listener {
+    fn on_event(&self, event: String);
+}
+
+#[jni_class]
+struct Watcher {}
+
+#[jni_struct_impl]
+impl Watcher {
+    fn keep(&mut self, _listener: impl Listener + Send + 'static) {}
+}
+
+fn main() {}
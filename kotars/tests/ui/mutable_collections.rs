@@ -0,0 +1,16 @@
+use std::collections::HashMap;
+
+use kotars::{jni_class, jni_init, jni_struct_impl};
+
+jni_init!("");
+
+#[jni_class]
+struct Registry {}
+
+#[jni_struct_impl]
+impl Registry {
+    fn fill_map(&self, _entries: &mut HashMap<String, i32>) {}
+    fn fill_names(&self, _names: &mut Vec<String>) {}
+}
+
+fn main() {}